@@ -0,0 +1,226 @@
+use crate::class::case_variants;
+use crate::compile::{Inst, Program};
+
+/// Capture group spans as byte offsets, index 0 holds the whole match
+pub type Spans = Vec<Option<(usize, usize)>>;
+
+/// Pairs up the start and end slots of each capture group
+pub fn spans(slots: &[Option<usize>]) -> Spans {
+    slots
+        .chunks(2)
+        .map(|pair| match pair {
+            [Some(start), Some(end)] => Some((*start, *end)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Finds the leftmost match starting at or after `start` by following one path of the program at a
+/// time, the alternatives left to try are kept on an explicit stack so long lines cannot overflow
+/// the call stack
+pub fn find_at(program: &Program, case_insensitive: bool, input: &str, start: usize) -> Option<Spans> {
+    find(program, case_insensitive, input, start, false)
+}
+
+/// Like [`find_at`] but passes over empty matches, so an alternative matching some text at the
+/// same position wins instead
+pub fn find_non_empty_at(program: &Program, case_insensitive: bool, input: &str, start: usize) -> Option<Spans> {
+    find(program, case_insensitive, input, start, true)
+}
+
+fn find(program: &Program, case_insensitive: bool, input: &str, start: usize, non_empty: bool) -> Option<Spans> {
+    let mut backtracker = Backtracker {
+        program,
+        input,
+        case_insensitive,
+        non_empty,
+        stack: Vec::new(),
+        slots: vec![None; program.slots],
+        entered: vec![None; program.insts.len()],
+    };
+    let mut pos = start;
+    loop {
+        if backtracker.run(pos) {
+            return Some(spans(&backtracker.slots));
+        }
+        pos += input[pos..].chars().next()?.len_utf8();
+    }
+}
+
+enum Frame {
+    Explore(usize, usize),
+    RestoreSlot(usize, Option<usize>),
+    RestoreEntered(usize, Option<usize>),
+}
+
+struct Backtracker<'a> {
+    program: &'a Program,
+    input: &'a str,
+    case_insensitive: bool,
+    non_empty: bool,
+    stack: Vec<Frame>,
+    slots: Vec<Option<usize>>,
+    /// Position at which the current path last entered each instruction
+    entered: Vec<Option<usize>>,
+}
+
+impl Backtracker<'_> {
+    fn run(&mut self, start: usize) -> bool {
+        self.stack.clear();
+        self.slots.fill(None);
+        self.entered.fill(None);
+        self.stack.push(Frame::Explore(0, start));
+        while let Some(frame) = self.stack.pop() {
+            match frame {
+                Frame::Explore(pc, pos) => {
+                    if self.explore(pc, pos) {
+                        return true;
+                    }
+                }
+                Frame::RestoreSlot(slot, value) => self.slots[slot] = value,
+                Frame::RestoreEntered(pc, value) => self.entered[pc] = value,
+            }
+        }
+        false
+    }
+
+    /// Follows the preferred path from `pc` until it matches or fails, pushing the other
+    /// alternatives and the state to restore before trying them
+    fn explore(&mut self, mut pc: usize, mut pos: usize) -> bool {
+        loop {
+            // entering an instruction again without consuming input is an empty loop iteration,
+            // the Pike VM drops that thread as well
+            if self.entered[pc] == Some(pos) {
+                return false;
+            }
+            self.stack.push(Frame::RestoreEntered(pc, self.entered[pc]));
+            self.entered[pc] = Some(pos);
+            match &self.program.insts[pc] {
+                Inst::Char(expected) => match self.input[pos..].chars().next() {
+                    Some(c) if c == *expected => pos += c.len_utf8(),
+                    _ => return false,
+                },
+                Inst::Class(set) => match self.input[pos..].chars().next() {
+                    Some(c) if set.contains(c) => pos += c.len_utf8(),
+                    _ => return false,
+                },
+                Inst::Look(look) => {
+                    if !look.holds(self.input, pos) {
+                        return false;
+                    }
+                }
+                Inst::Save(slot) => {
+                    self.save(*slot, Some(pos));
+                    if slot % 2 == 0 {
+                        // a group being matched again has no text to refer back to until it closes
+                        self.save(slot + 1, None);
+                    }
+                }
+                Inst::Split(first, second) => {
+                    self.stack.push(Frame::Explore(*second, pos));
+                    pc = *first;
+                    continue;
+                }
+                Inst::Jmp(to) => {
+                    pc = *to;
+                    continue;
+                }
+                Inst::Match(_) => return !self.non_empty || self.slots[0] != Some(pos),
+                Inst::Backref(group) => match (self.slots[2 * group], self.slots[2 * group + 1]) {
+                    (Some(start), Some(end)) => match self.match_captured(&self.input[start..end], pos) {
+                        Some(next) => pos = next,
+                        None => return false,
+                    },
+                    _ => return false,
+                },
+            }
+            pc += 1;
+        }
+    }
+
+    fn save(&mut self, slot: usize, value: Option<usize>) {
+        self.stack.push(Frame::RestoreSlot(slot, self.slots[slot]));
+        self.slots[slot] = value;
+    }
+
+    /// Returns the position after `captured` when it occurs at `pos`, folding case if requested
+    fn match_captured(&self, captured: &str, pos: usize) -> Option<usize> {
+        if !self.case_insensitive {
+            return self.input[pos..].starts_with(captured).then_some(pos + captured.len());
+        }
+        let mut input = self.input[pos..].chars();
+        let mut next = pos;
+        for expected in captured.chars() {
+            let c = input.next()?;
+            if c != expected && !case_variants(expected).contains(&c) {
+                return None;
+            }
+            next += c.len_utf8();
+        }
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::compile_backtrack;
+    use crate::parser::parse;
+
+    fn find(input: &str, pattern: &str) -> Option<Spans> {
+        find_at(&compile_backtrack(&parse(pattern).unwrap()), false, input, 0)
+    }
+
+    #[test]
+    fn find_leftmost_match() {
+        assert_eq!(find("trust", "rust"), Some(vec![Some((1, 5))]));
+        assert_eq!(find("rust", "scala"), None);
+        assert_eq!(find("", "a*"), Some(vec![Some((0, 0))]));
+    }
+
+    #[test]
+    fn find_captures() {
+        assert_eq!(find("abab", "(ab)+"), Some(vec![Some((0, 4)), Some((2, 4))]));
+        assert_eq!(find("ct", "c(a)?t"), Some(vec![Some((0, 2)), None]));
+        assert_eq!(find("r r", "((r) \\2)"), Some(vec![Some((0, 3)), Some((0, 3)), Some((0, 1))]));
+    }
+
+    #[test]
+    fn backtrack_into_repeats() {
+        assert_eq!(find("baaag", "ba+ag"), Some(vec![Some((0, 5))]));
+        assert_eq!(find("rust", "rus.?t"), Some(vec![Some((0, 4))]));
+        assert_eq!(find("aaaacc", "a{1,}cc"), Some(vec![Some((0, 6))]));
+    }
+
+    #[test]
+    fn match_backreference_ignoring_case() {
+        let ast = crate::parser::parse_with("(\\w+) \\1", crate::parser::Flags { case_insensitive: true, ..Default::default() }).unwrap();
+        assert_eq!(find_at(&compile_backtrack(&ast), true, "Rust rUST", 0).map(|c| c[0]), Some(Some((0, 9))));
+        assert_eq!(find("Rust rUST", "(\\w+) \\1"), None);
+    }
+
+    #[test]
+    fn match_long_lines() {
+        let line = format!("x{}x", "a".repeat(200_000));
+        assert_eq!(find(&line, "(x).*\\1"), Some(vec![Some((0, line.len())), Some((0, 1))]));
+        assert_eq!(find(&line, "(a)\\1*x"), Some(vec![Some((1, line.len())), Some((1, 2))]));
+    }
+
+    #[test]
+    fn skip_empty_iterations() {
+        assert_eq!(find("aa", "(|a)?"), Some(vec![Some((0, 0)), Some((0, 0))]));
+        assert_eq!(find("a abb", "(|a){1,3}.{1,3}"), Some(vec![Some((0, 3)), Some((0, 0))]));
+        assert_eq!(find("ab", "(a|)*b"), Some(vec![Some((0, 2)), Some((0, 1))]));
+    }
+
+    #[test]
+    fn refer_to_open_group() {
+        assert_eq!(find("aa", "(a\\1)"), None);
+        assert_eq!(find("aa", "(a)\\1"), Some(vec![Some((0, 2)), Some((0, 1))]));
+    }
+
+    #[test]
+    fn match_multibyte_input() {
+        assert_eq!(find("¾®_ediœ1", "\\wedi[^x]\\d"), Some(vec![Some((4, 11))]));
+    }
+}
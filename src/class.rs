@@ -0,0 +1,298 @@
+use crate::unicode_tables::case_folding::CASE_FOLDING;
+use crate::unicode_tables::{general_category, perl, property, script, Table};
+use std::cmp::Ordering;
+
+/// Set of characters kept as sorted, non-overlapping inclusive ranges
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClassSet {
+    ranges: Vec<(char, char)>,
+}
+
+impl ClassSet {
+    pub fn new() -> Self {
+        ClassSet { ranges: Vec::new() }
+    }
+
+    pub fn from_ranges(ranges: &[(char, char)]) -> Self {
+        let mut set = ClassSet { ranges: ranges.to_vec() };
+        set.canonicalize();
+        set
+    }
+
+    pub fn any() -> Self {
+        ClassSet::from_ranges(&[('\0', char::MAX)])
+    }
+
+    /// `\d`, the decimal digits of any script when `unicode` is set
+    pub fn digit(unicode: bool) -> Self {
+        if unicode {
+            ClassSet { ranges: perl::DIGIT.to_vec() }
+        } else {
+            ClassSet::from_ranges(&[('0', '9')])
+        }
+    }
+
+    /// `\s`, the `White_Space` property when `unicode` is set
+    pub fn space(unicode: bool) -> Self {
+        if unicode {
+            ClassSet { ranges: perl::SPACE.to_vec() }
+        } else {
+            ClassSet::from_ranges(&[('\t', '\r'), (' ', ' ')])
+        }
+    }
+
+    /// `\w`, letters, marks, digits and connector punctuation of any script when `unicode` is set
+    pub fn word(unicode: bool) -> Self {
+        if unicode {
+            ClassSet { ranges: perl::WORD.to_vec() }
+        } else {
+            ClassSet::from_ranges(&[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')])
+        }
+    }
+
+    /// POSIX bracket class like `alpha` in `[[:alpha:]]`, with its meaning in the C locale
+    pub fn posix(name: &str) -> Option<Self> {
+        let ranges: &[(char, char)] = match name {
+            "alnum" => &[('0', '9'), ('A', 'Z'), ('a', 'z')],
+            "alpha" => &[('A', 'Z'), ('a', 'z')],
+            "blank" => &[('\t', '\t'), (' ', ' ')],
+            "cntrl" => &[('\0', '\x1F'), ('\x7F', '\x7F')],
+            "digit" => &[('0', '9')],
+            "graph" => &[('!', '~')],
+            "lower" => &[('a', 'z')],
+            "print" => &[(' ', '~')],
+            "punct" => &[('!', '/'), (':', '@'), ('[', '`'), ('{', '~')],
+            "space" => &[('\t', '\r'), (' ', ' ')],
+            "upper" => &[('A', 'Z')],
+            "xdigit" => &[('0', '9'), ('A', 'F'), ('a', 'f')],
+            _ => return None,
+        };
+        Some(ClassSet::from_ranges(ranges))
+    }
+
+    /// General category, script or binary property written as `name` or `key=value`,
+    /// names are compared ignoring case, spaces, underscores and dashes
+    pub fn unicode_property(query: &str) -> Option<Self> {
+        let (key, value) = match query.split_once(['=', ':']) {
+            Some((key, value)) => (Some(loose_name(key)), value),
+            None => (None, query),
+        };
+        let value = loose_name(value);
+        let lookup = |tables: &[(&str, &[Table])], value: &str| {
+            let (_, members) = tables.iter().find(|(name, _)| loose_name(name) == value)?;
+            Some(ClassSet::from_ranges(&members.concat()))
+        };
+        match key.as_deref() {
+            Some("gc" | "generalcategory") => lookup(general_category::BY_NAME, &value),
+            Some("sc" | "script") => lookup(script::BY_NAME, &value),
+            Some(_) => None,
+            None => match value.as_str() {
+                "any" => Some(ClassSet::any()),
+                "ascii" => Some(ClassSet::from_ranges(&[('\0', '\x7F')])),
+                "assigned" => lookup(general_category::BY_NAME, "cn").map(|mut unassigned| {
+                    unassigned.negate();
+                    unassigned
+                }),
+                _ => lookup(general_category::BY_NAME, &value)
+                    .or_else(|| lookup(script::BY_NAME, &value))
+                    .or_else(|| lookup(property::BY_NAME, &value)),
+            },
+        }
+    }
+
+    pub fn ranges(&self) -> &[(char, char)] {
+        &self.ranges
+    }
+
+    /// Number of chars in the set
+    pub fn len(&self) -> usize {
+        self.ranges
+            .iter()
+            .map(|&(start, end)| {
+                let surrogates = if start <= '\u{D7FF}' && end >= '\u{E000}' { 0x800 } else { 0 };
+                end as usize - start as usize + 1 - surrogates
+            })
+            .sum()
+    }
+
+    pub fn push(&mut self, start: char, end: char) {
+        self.ranges.push((start, end));
+        self.canonicalize();
+    }
+
+    pub fn union(&mut self, other: &ClassSet) {
+        self.ranges.extend_from_slice(&other.ranges);
+        self.canonicalize();
+    }
+
+    pub fn negate(&mut self) {
+        let mut negated = Vec::new();
+        let mut next = Some('\0');
+        for &(start, end) in &self.ranges {
+            if let Some(from) = next
+                && from < start
+            {
+                negated.push((from, decrement(start)));
+            }
+            next = increment(end);
+        }
+        if let Some(from) = next {
+            negated.push((from, char::MAX));
+        }
+        self.ranges = negated;
+    }
+
+    /// Adds every char that shares a simple case folding with a member of the set
+    pub fn case_fold(&mut self) {
+        let mut folded = Vec::new();
+        for &(start, end) in &self.ranges {
+            let from = CASE_FOLDING.partition_point(|&(c, _)| c < start);
+            for &(_, variants) in CASE_FOLDING[from..].iter().take_while(|&&(c, _)| c <= end) {
+                folded.extend(variants.iter().map(|&v| (v, v)));
+            }
+        }
+        self.ranges.extend(folded);
+        self.canonicalize();
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        self.ranges
+            .binary_search_by(|&(start, end)| compare_range(start, end, c))
+            .is_ok()
+    }
+
+    fn canonicalize(&mut self) {
+        self.ranges.sort();
+        let mut merged: Vec<(char, char)> = Vec::with_capacity(self.ranges.len());
+        for &(start, end) in &self.ranges {
+            match merged.last_mut() {
+                Some(last) if increment(last.1).is_none_or(|next| start <= next) => {
+                    last.1 = last.1.max(end);
+                }
+                _ => merged.push((start, end)),
+            }
+        }
+        self.ranges = merged;
+    }
+}
+
+/// Whether `c` belongs to [`ClassSet::word`]
+pub fn is_word_char(c: char, unicode: bool) -> bool {
+    if unicode && !c.is_ascii() {
+        perl::WORD.binary_search_by(|&(start, end)| compare_range(start, end, c)).is_ok()
+    } else {
+        c.is_ascii_alphanumeric() || c == '_'
+    }
+}
+
+/// Other chars sharing a simple case folding with `c`
+pub fn case_variants(c: char) -> &'static [char] {
+    match CASE_FOLDING.binary_search_by_key(&c, |&(c, _)| c) {
+        Ok(i) => CASE_FOLDING[i].1,
+        Err(_) => &[],
+    }
+}
+
+fn loose_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, ' ' | '_' | '-'))
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn compare_range(start: char, end: char, c: char) -> Ordering {
+    if end < c {
+        Ordering::Less
+    } else if start > c {
+        Ordering::Greater
+    } else {
+        Ordering::Equal
+    }
+}
+
+fn increment(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        char::MAX => None,
+        _ => char::from_u32(c as u32 + 1),
+    }
+}
+
+fn decrement(c: char) -> char {
+    match c {
+        '\u{E000}' => '\u{D7FF}',
+        _ => char::from_u32(c as u32 - 1).unwrap_or(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_ranges() {
+        let set = ClassSet::from_ranges(&[('d', 'f'), ('a', 'c'), ('x', 'x'), ('e', 'g')]);
+        assert_eq!(set.ranges, vec![('a', 'g'), ('x', 'x')]);
+    }
+
+    #[test]
+    fn negate_ranges() {
+        let mut set = ClassSet::from_ranges(&[('\0', 'a'), ('c', 'c')]);
+        set.negate();
+        assert_eq!(set.ranges, vec![('b', 'b'), ('d', char::MAX)]);
+        set.negate();
+        assert_eq!(set.ranges, vec![('\0', 'a'), ('c', 'c')]);
+        let mut any = ClassSet::any();
+        any.negate();
+        assert_eq!(any, ClassSet::new());
+    }
+
+    #[test]
+    fn fold_case() {
+        let mut set = ClassSet::from_ranges(&[('a', 'c'), ('k', 'k'), ('1', '1')]);
+        set.case_fold();
+        assert_eq!(set, ClassSet::from_ranges(&[('1', '1'), ('A', 'C'), ('K', 'K'), ('a', 'c'), ('k', 'k'), ('\u{212A}', '\u{212A}')]));
+        assert_eq!(case_variants('σ'), &['Σ', 'ς']);
+        assert_eq!(case_variants('ß'), &['ẞ']);
+        assert_eq!(case_variants('\u{390}'), &['\u{1FD3}']);
+        assert_eq!(case_variants('\u{FB06}'), &['\u{FB05}']);
+        assert!(case_variants('1').is_empty());
+    }
+
+    #[test]
+    fn contains_char() {
+        let set = ClassSet::word(false);
+        assert!(set.contains('a'));
+        assert!(set.contains('_'));
+        assert!(set.contains('9'));
+        assert!(!set.contains('-'));
+        assert!(!set.contains('œ'));
+    }
+
+    #[test]
+    fn unicode_perl_classes() {
+        assert!(ClassSet::word(true).contains('œ'));
+        assert!(ClassSet::word(true).contains('\u{301}'));
+        assert!(!ClassSet::word(true).contains('¼'));
+        assert!(ClassSet::digit(true).contains('٣'));
+        assert!(!ClassSet::digit(false).contains('٣'));
+        assert!(ClassSet::space(true).contains('\u{A0}'));
+        assert!(!ClassSet::space(false).contains('\u{A0}'));
+        assert!(is_word_char('é', true) && !is_word_char('é', false));
+        assert!(!is_word_char('‿', false) && is_word_char('‿', true));
+    }
+
+    #[test]
+    fn look_up_unicode_properties() {
+        let property = |query| ClassSet::unicode_property(query).unwrap();
+        assert!(property("L").contains('ж') && !property("L").contains('1'));
+        assert_eq!(property("Lu"), property("uppercase letter"));
+        assert_eq!(property("gc=Nd"), property("Decimal_Number"));
+        assert!(property("Greek").contains('λ') && !property("sc:greek").contains('l'));
+        assert!(property("White_Space").contains('\u{2003}'));
+        assert!(property("ASCII").contains('~') && !property("ASCII").contains('é'));
+        assert!(property("Assigned").contains('a') && !property("Assigned").contains('\u{378}'));
+        assert_eq!(ClassSet::unicode_property("Klingon"), None);
+        assert_eq!(ClassSet::unicode_property("sc=Lu"), None);
+    }
+}
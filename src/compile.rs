@@ -0,0 +1,238 @@
+use crate::class::ClassSet;
+use crate::parser::{Ast, Look, Node};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inst {
+    Char(char),
+    Class(ClassSet),
+    Look(Look),
+    Save(usize),
+    /// Epsilon fork, the first target has priority
+    Split(usize, usize),
+    Jmp(usize),
+    /// Accepts a match of the pattern with this index
+    Match(usize),
+    /// Matches the text of this capture group again, only the backtracker runs it
+    Backref(usize),
+}
+
+/// Thompson NFA, slots `2i` and `2i + 1` hold the start and end of capture group `i`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub insts: Vec<Inst>,
+    pub slots: usize,
+}
+
+/// Compiles the pattern tree into an NFA, patterns with backreferences are not regular and yield `None`
+pub fn compile(ast: &Ast) -> Option<Program> {
+    compile_with(ast, false)
+}
+
+/// Compiles the pattern tree for the backtracker, which also runs backreferences
+pub fn compile_backtrack(ast: &Ast) -> Program {
+    compile_with(ast, true).expect("backreferences compile for the backtracker")
+}
+
+fn compile_with(ast: &Ast, backreferences: bool) -> Option<Program> {
+    let mut compiler = Compiler { insts: Vec::new(), captures: true, backreferences };
+    compiler.push(Inst::Save(0));
+    compiler.compile(&ast.root)?;
+    compiler.push(Inst::Save(1));
+    compiler.push(Inst::Match(0));
+    Some(Program {
+        insts: compiler.insts,
+        slots: 2 * (ast.groups + 1),
+    })
+}
+
+/// Compiles several patterns into one NFA that tries them in order and ends each in its own
+/// `Match`, captures are left out. Yields `None` for no patterns or one with backreferences.
+pub fn compile_set(asts: &[(usize, &Ast)]) -> Option<Program> {
+    let mut compiler = Compiler { insts: Vec::new(), captures: false, backreferences: false };
+    let (last, rest) = asts.split_last()?;
+    for (pattern, ast) in rest {
+        let split = compiler.push(Inst::Split(compiler.insts.len() + 1, 0));
+        compiler.compile(&ast.root)?;
+        compiler.push(Inst::Match(*pattern));
+        let next = compiler.insts.len();
+        compiler.patch(split, next);
+    }
+    compiler.compile(&last.1.root)?;
+    compiler.push(Inst::Match(last.0));
+    Some(Program { insts: compiler.insts, slots: 0 })
+}
+
+struct Compiler {
+    insts: Vec<Inst>,
+    captures: bool,
+    backreferences: bool,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> usize {
+        self.insts.push(inst);
+        self.insts.len() - 1
+    }
+
+    fn patch(&mut self, pc: usize, target: usize) {
+        match &mut self.insts[pc] {
+            Inst::Jmp(to) => *to = target,
+            Inst::Split(_, to) => *to = target,
+            _ => unreachable!("only jumps and splits are patched"),
+        }
+    }
+
+    fn compile(&mut self, node: &Node) -> Option<()> {
+        match node {
+            Node::Empty => {}
+            Node::Literal(c) => {
+                self.push(Inst::Char(*c));
+            }
+            Node::Class(set) => {
+                self.push(Inst::Class(set.clone()));
+            }
+            Node::Look(look) => {
+                self.push(Inst::Look(*look));
+            }
+            Node::Group { node, .. } if !self.captures => self.compile(node)?,
+            Node::Group { index, node } => {
+                self.push(Inst::Save(2 * index));
+                self.compile(node)?;
+                self.push(Inst::Save(2 * index + 1));
+            }
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node)?;
+                }
+            }
+            Node::Alternation(branches) => {
+                let mut jumps = Vec::new();
+                for (i, branch) in branches.iter().enumerate() {
+                    if i + 1 < branches.len() {
+                        let split = self.push(Inst::Split(self.insts.len() + 1, 0));
+                        self.compile(branch)?;
+                        jumps.push(self.push(Inst::Jmp(0)));
+                        let next = self.insts.len();
+                        self.patch(split, next);
+                    } else {
+                        self.compile(branch)?;
+                    }
+                }
+                let end = self.insts.len();
+                for jump in jumps {
+                    self.patch(jump, end);
+                }
+            }
+            Node::Repeat { node, min, max } => self.compile_repeat(node, *min, *max)?,
+            Node::Backreference(index) if self.backreferences => {
+                self.push(Inst::Backref(*index));
+            }
+            Node::Backreference(_) => return None,
+        }
+        Some(())
+    }
+
+    fn compile_repeat(&mut self, node: &Node, min: u32, max: Option<u32>) -> Option<()> {
+        for _ in 0..min {
+            self.compile(node)?;
+        }
+        match max {
+            None => {
+                let split = self.push(Inst::Split(self.insts.len() + 1, 0));
+                self.compile(node)?;
+                self.push(Inst::Jmp(split));
+                let end = self.insts.len();
+                self.patch(split, end);
+            }
+            Some(max) => {
+                let mut splits = Vec::new();
+                for _ in min..max {
+                    splits.push(self.push(Inst::Split(self.insts.len() + 1, 0)));
+                    self.compile(node)?;
+                }
+                let end = self.insts.len();
+                for split in splits {
+                    self.patch(split, end);
+                }
+            }
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn insts(pattern: &str) -> Vec<Inst> {
+        compile(&parse(pattern).unwrap()).unwrap().insts
+    }
+
+    #[test]
+    fn compile_alternation() {
+        assert_eq!(insts("a|b"), vec![
+            Inst::Save(0),
+            Inst::Split(2, 4),
+            Inst::Char('a'),
+            Inst::Jmp(5),
+            Inst::Char('b'),
+            Inst::Save(1),
+            Inst::Match(0),
+        ]);
+    }
+
+    #[test]
+    fn estimate_program_size() {
+        for pattern in ["a|b|(c)", "(ab)+", "(a|bc){2,5}d*", "x?(y{3}){2,}", "^\\w+$"] {
+            let ast = parse(pattern).unwrap();
+            // compile adds the saves of group 0 and the match
+            assert!(compile(&ast).unwrap().insts.len() <= ast.root.program_size() + 3, "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn compile_repeats() {
+        assert_eq!(insts("a+"), vec![
+            Inst::Save(0),
+            Inst::Char('a'),
+            Inst::Split(3, 5),
+            Inst::Char('a'),
+            Inst::Jmp(2),
+            Inst::Save(1),
+            Inst::Match(0),
+        ]);
+        assert_eq!(insts("(a){1,2}"), vec![
+            Inst::Save(0),
+            Inst::Save(2),
+            Inst::Char('a'),
+            Inst::Save(3),
+            Inst::Split(5, 8),
+            Inst::Save(2),
+            Inst::Char('a'),
+            Inst::Save(3),
+            Inst::Save(1),
+            Inst::Match(0),
+        ]);
+    }
+
+    #[test]
+    fn compile_sets() {
+        let (first, second) = (parse("(a)").unwrap(), parse("b").unwrap());
+        assert_eq!(compile_set(&[(0, &first), (2, &second)]).unwrap().insts, vec![
+            Inst::Split(1, 3),
+            Inst::Char('a'),
+            Inst::Match(0),
+            Inst::Char('b'),
+            Inst::Match(2),
+        ]);
+        assert_eq!(compile_set(&[]), None);
+    }
+
+    #[test]
+    fn skip_backreferences() {
+        assert_eq!(compile(&parse("(a)\\1").unwrap()), None);
+        assert_eq!(compile(&parse("(a)\\d").unwrap()).map(|p| p.slots), Some(4));
+        assert_eq!(compile_backtrack(&parse("(a)\\1").unwrap()).insts[4], Inst::Backref(1));
+    }
+}
@@ -0,0 +1,500 @@
+use crate::class::{is_word_char, ClassSet};
+use crate::compile::{Inst, Program};
+use crate::parser::Look;
+use std::collections::HashMap;
+
+/// Number of cached states after which the cache is cleared
+const MAX_STATES: usize = 4096;
+
+/// Clears tolerated before the DFA checks how much input each cleared cache served
+const MIN_CLEARS: usize = 3;
+
+/// Chars that have to be read per cached state between clears, below that the cache is kept
+/// as it is and lines needing another state are left to the NFA
+const MIN_CHARS_PER_STATE: usize = 10;
+
+const UNKNOWN: usize = usize::MAX;
+
+/// Lazily built DFA answering whether a line contains a match, states are sets of NFA
+/// instructions created on first use and memoized together with their transitions
+pub struct Dfa {
+    alphabet: Alphabet,
+    states: Vec<State>,
+    ids: HashMap<(Box<[usize]>, u8, bool), usize>,
+    transitions: Vec<usize>,
+    start: Option<usize>,
+    clears: usize,
+    /// Chars read since the cache was last cleared
+    scanned: usize,
+    /// Set once clearing is not worth it
+    frozen: bool,
+    /// Word bits the word assertions of the program ask for, see [`word_bit`]
+    word_mask: u8,
+    stack: Vec<usize>,
+    seen: Vec<bool>,
+    visited: Vec<usize>,
+}
+
+struct State {
+    /// Besides the instructions that consume input or match, the assertions waiting for the next char
+    insts: Box<[usize]>,
+    /// Patterns whose `Match` is among the instructions
+    patterns: Box<[usize]>,
+    /// Word bits of the char read last, which word assertions look back at
+    word: u8,
+    /// Only the state before the first char is at the start of the input
+    at_start: bool,
+    eoi_match: Option<bool>,
+}
+
+/// What is known about the position a closure is taken at
+#[derive(Clone, Copy)]
+struct Context {
+    at_start: bool,
+    at_end: bool,
+    /// Word bits of the char before the position
+    before: u8,
+    /// Word bits of the char after the position, `None` while it is not read yet
+    after: Option<u8>,
+}
+
+impl Dfa {
+    /// Returns `None` for programs with backreferences, which are not regular
+    pub fn new(program: &Program) -> Option<Dfa> {
+        if program.insts.iter().any(|inst| matches!(inst, Inst::Backref(_))) {
+            return None;
+        }
+        let word_mask = program.insts.iter().fold(0, |mask, inst| match inst {
+            Inst::Look(look) => mask | look_word_bit(*look),
+            _ => mask,
+        });
+        Some(Dfa {
+            alphabet: Alphabet::new(program, word_mask),
+            states: Vec::new(),
+            ids: HashMap::new(),
+            transitions: Vec::new(),
+            start: None,
+            clears: 0,
+            scanned: 0,
+            frozen: false,
+            word_mask,
+            stack: Vec::new(),
+            seen: vec![false; program.insts.len()],
+            visited: Vec::new(),
+        })
+    }
+
+    /// Returns `None` when the state cache is full and clearing it is no longer worth it
+    pub fn is_match(&mut self, program: &Program, input: &str) -> Option<bool> {
+        if input.is_empty() {
+            let insts = self.closure(program, &[0], Context::EMPTY_INPUT);
+            return Some(patterns(program, &insts).next().is_some());
+        }
+        let mut state = self.start(program)?;
+        for c in input.chars() {
+            if !self.states[state].patterns.is_empty() {
+                return Some(true);
+            }
+            state = self.next(program, state, c)?;
+            if self.states[state].insts.is_empty() {
+                return Some(false);
+            }
+        }
+        Some(self.eoi_match(program, state))
+    }
+
+    /// Marks every pattern of a set program that matches somewhere in `input`,
+    /// returns `None` when the state cache is full and clearing it is no longer worth it
+    pub fn which_match(&mut self, program: &Program, input: &str, matched: &mut [bool]) -> Option<()> {
+        if input.is_empty() {
+            let insts = self.closure(program, &[0], Context::EMPTY_INPUT);
+            patterns(program, &insts).for_each(|pattern| matched[pattern] = true);
+            return Some(());
+        }
+        let mut state = self.start(program)?;
+        for c in input.chars() {
+            self.states[state].patterns.iter().for_each(|&pattern| matched[pattern] = true);
+            state = self.next(program, state, c)?;
+            if self.states[state].insts.is_empty() {
+                return Some(());
+            }
+        }
+        let insts = self.eoi_closure(program, state);
+        patterns(program, &insts).for_each(|pattern| matched[pattern] = true);
+        Some(())
+    }
+
+    fn start(&mut self, program: &Program) -> Option<usize> {
+        if let Some(state) = self.start {
+            return Some(state);
+        }
+        let context = Context { at_start: true, at_end: false, before: 0, after: None };
+        let insts = self.closure(program, &[0], context);
+        let state = self.add_state(program, insts, 0, true)?;
+        self.start = Some(state);
+        Some(state)
+    }
+
+    fn next(&mut self, program: &Program, state: usize, c: char) -> Option<usize> {
+        self.scanned += 1;
+        let index = state * self.alphabet.len + self.alphabet.class(c);
+        if self.transitions[index] == UNKNOWN {
+            let clears = self.clears;
+            let next = self.step(program, state, c)?;
+            // after a clear `index` belongs to another state
+            if self.clears != clears {
+                return Some(next);
+            }
+            self.transitions[index] = next;
+        }
+        Some(self.transitions[index])
+    }
+
+    fn step(&mut self, program: &Program, state: usize, c: char) -> Option<usize> {
+        let word = word_bits(c) & self.word_mask;
+        let State { insts: current, word: before, at_start, .. } = &self.states[state];
+        let (current, before, at_start) = (current.clone(), *before, *at_start);
+        // the waiting word assertions can be decided now that the char after them is known
+        let waiting: Vec<usize> = current.iter().copied().filter(|&pc| waits_for_next_char(&program.insts[pc])).collect();
+        let resolved = if waiting.is_empty() {
+            Box::default()
+        } else {
+            self.closure(program, &waiting, Context { at_start, at_end: false, before, after: Some(word) })
+        };
+        let mut seeds: Vec<usize> = current
+            .iter()
+            .chain(resolved.iter())
+            .filter(|&&pc| match &program.insts[pc] {
+                Inst::Char(expected) => *expected == c,
+                Inst::Class(set) => set.contains(c),
+                _ => false,
+            })
+            .map(|pc| pc + 1)
+            .collect();
+        // unanchored search restarts the program at every position
+        seeds.push(0);
+        let context = Context { at_start: false, at_end: false, before: word, after: None };
+        let mut insts = self.closure(program, &seeds, context).into_vec();
+        // a match found before `c` by deciding an assertion is reported by the next state
+        insts.extend(resolved.iter().filter(|&&pc| matches!(program.insts[pc], Inst::Match(_))));
+        insts.sort_unstable();
+        insts.dedup();
+        self.add_state(program, insts.into_boxed_slice(), word, false)
+    }
+
+    fn eoi_match(&mut self, program: &Program, state: usize) -> bool {
+        if let Some(matched) = self.states[state].eoi_match {
+            return matched;
+        }
+        let insts = self.eoi_closure(program, state);
+        let matched = patterns(program, &insts).next().is_some();
+        self.states[state].eoi_match = Some(matched);
+        matched
+    }
+
+    /// Instructions of `state` that are left once the end of the input is reached
+    fn eoi_closure(&mut self, program: &Program, state: usize) -> Box<[usize]> {
+        let seeds: Vec<usize> = self.states[state]
+            .insts
+            .iter()
+            .copied()
+            .filter(|&pc| {
+                let inst = &program.insts[pc];
+                matches!(inst, Inst::Look(Look::End) | Inst::Match(_)) || waits_for_next_char(inst)
+            })
+            .collect();
+        let before = self.states[state].word;
+        self.closure(program, &seeds, Context { at_start: false, at_end: true, before, after: Some(0) })
+    }
+
+    fn add_state(&mut self, program: &Program, insts: Box<[usize]>, word: u8, at_start: bool) -> Option<usize> {
+        let key = (insts, word, at_start);
+        if let Some(&id) = self.ids.get(&key) {
+            return Some(id);
+        }
+        if self.states.len() >= MAX_STATES {
+            self.clear_cache()?;
+        }
+        let id = self.states.len();
+        let patterns = patterns(program, &key.0).collect();
+        let insts = key.0.clone();
+        self.ids.insert(key, id);
+        self.states.push(State { insts, patterns, word, at_start, eoi_match: None });
+        self.transitions.extend(std::iter::repeat_n(UNKNOWN, self.alphabet.len));
+        Some(id)
+    }
+
+    /// Drops every cached state, unless the cache served so few chars since the last clear that
+    /// rebuilding it costs more than running the NFA, then it is kept for good
+    fn clear_cache(&mut self) -> Option<()> {
+        if self.frozen || self.clears >= MIN_CLEARS && self.scanned < MIN_CHARS_PER_STATE * MAX_STATES {
+            self.frozen = true;
+            return None;
+        }
+        self.states.clear();
+        self.ids.clear();
+        self.transitions.clear();
+        self.start = None;
+        self.clears += 1;
+        self.scanned = 0;
+        Some(())
+    }
+
+    /// Follows epsilon transitions from `seeds` and keeps the instructions that consume input,
+    /// match, wait for the end of the line or, while `context` lacks it, for the next char
+    fn closure(&mut self, program: &Program, seeds: &[usize], context: Context) -> Box<[usize]> {
+        let mut insts = Vec::new();
+        self.stack.extend(seeds.iter().rev());
+        while let Some(pc) = self.stack.pop() {
+            if self.seen[pc] {
+                continue;
+            }
+            self.seen[pc] = true;
+            self.visited.push(pc);
+            match &program.insts[pc] {
+                Inst::Jmp(to) => self.stack.push(*to),
+                Inst::Split(first, second) => {
+                    self.stack.push(*second);
+                    self.stack.push(*first);
+                }
+                Inst::Save(_) => self.stack.push(pc + 1),
+                Inst::Look(Look::Start) if context.at_start => self.stack.push(pc + 1),
+                Inst::Look(Look::Start) => {}
+                Inst::Look(Look::End) if context.at_end => self.stack.push(pc + 1),
+                Inst::Look(Look::End) | Inst::Char(_) | Inst::Class(_) | Inst::Match(_) => insts.push(pc),
+                Inst::Look(look) => match context.after {
+                    Some(after) => {
+                        let word_before = |unicode| context.before & word_bit(unicode) != 0;
+                        if look.holds_around(word_before, |unicode| after & word_bit(unicode) != 0) {
+                            self.stack.push(pc + 1);
+                        }
+                    }
+                    None => insts.push(pc),
+                },
+                // rejected by `Dfa::new`
+                Inst::Backref(_) => {}
+            }
+        }
+        for pc in self.visited.drain(..) {
+            self.seen[pc] = false;
+        }
+        insts.sort_unstable();
+        insts.into_boxed_slice()
+    }
+}
+
+impl Context {
+    /// Both edges of the input with no char in between
+    const EMPTY_INPUT: Context = Context { at_start: true, at_end: true, before: 0, after: Some(0) };
+}
+
+/// Word assertions depend on the char after their position as well as the one before
+fn waits_for_next_char(inst: &Inst) -> bool {
+    matches!(inst, Inst::Look(look) if !matches!(look, Look::Start | Look::End))
+}
+
+/// Bit set in a char's word bits when it is a word char for assertions with this `unicode` flag
+fn word_bit(unicode: bool) -> u8 {
+    if unicode { 2 } else { 1 }
+}
+
+fn word_bits(c: char) -> u8 {
+    [false, true]
+        .into_iter()
+        .filter(|&unicode| is_word_char(c, unicode))
+        .fold(0, |bits, unicode| bits | word_bit(unicode))
+}
+
+fn look_word_bit(look: Look) -> u8 {
+    match look {
+        Look::Start | Look::End => 0,
+        Look::WordStartHalf { unicode }
+        | Look::WordEndHalf { unicode }
+        | Look::WordBoundary { unicode }
+        | Look::NotWordBoundary { unicode }
+        | Look::WordStart { unicode }
+        | Look::WordEnd { unicode } => word_bit(unicode),
+    }
+}
+
+fn patterns<'a>(program: &'a Program, insts: &'a [usize]) -> impl Iterator<Item = usize> + 'a {
+    insts.iter().filter_map(|&pc| match program.insts[pc] {
+        Inst::Match(pattern) => Some(pattern),
+        _ => None,
+    })
+}
+
+/// Partition of all chars into classes that every instruction of the program treats alike,
+/// and that word assertions asking for the bits of `word_mask` see as all word chars or none
+struct Alphabet {
+    boundaries: Vec<u32>,
+    ascii: [usize; 128],
+    len: usize,
+}
+
+impl Alphabet {
+    fn new(program: &Program, word_mask: u8) -> Self {
+        let mut boundaries = Vec::new();
+        for unicode in [false, true] {
+            if word_mask & word_bit(unicode) != 0 {
+                for &(start, end) in ClassSet::word(unicode).ranges() {
+                    boundaries.extend([start as u32, end as u32 + 1]);
+                }
+            }
+        }
+        for inst in &program.insts {
+            match inst {
+                Inst::Char(c) => boundaries.extend([*c as u32, *c as u32 + 1]),
+                Inst::Class(set) => {
+                    for &(start, end) in set.ranges() {
+                        boundaries.extend([start as u32, end as u32 + 1]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        boundaries.retain(|&b| b > 0);
+        boundaries.sort_unstable();
+        boundaries.dedup();
+        let mut alphabet = Alphabet { len: boundaries.len() + 1, boundaries, ascii: [0; 128] };
+        for c in 0..128u8 {
+            alphabet.ascii[c as usize] = alphabet.lookup(c as u32);
+        }
+        alphabet
+    }
+
+    fn lookup(&self, c: u32) -> usize {
+        self.boundaries.partition_point(|&b| b <= c)
+    }
+
+    fn class(&self, c: char) -> usize {
+        match self.ascii.get(c as usize) {
+            Some(&class) => class,
+            None => self.lookup(c as u32),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::compile;
+    use crate::parser::parse;
+
+    fn is_match(input: &str, pattern: &str) -> Option<bool> {
+        let program = compile(&parse(pattern).unwrap()).unwrap();
+        Dfa::new(&program).unwrap().is_match(&program, input)
+    }
+
+    #[test]
+    fn match_unanchored() {
+        assert_eq!(is_match("trust", "rust"), Some(true));
+        assert_eq!(is_match("rust", "scala"), Some(false));
+        assert_eq!(is_match("xab b", "(ab)+ ?b$"), Some(true));
+        assert_eq!(is_match("¾®_ediœ1", "\\wedi[^x]\\d"), Some(true));
+        assert_eq!(is_match("", "a*"), Some(true));
+    }
+
+    #[test]
+    fn match_anchors() {
+        assert_eq!(is_match("rust", "^ru"), Some(true));
+        assert_eq!(is_match("trust", "^ru"), Some(false));
+        assert_eq!(is_match("rust", "st$"), Some(true));
+        assert_eq!(is_match("rusty", "st$"), Some(false));
+        assert_eq!(is_match("", "^$"), Some(true));
+        assert_eq!(is_match("a", "^$"), Some(false));
+    }
+
+    #[test]
+    fn match_every_pattern_of_a_set() {
+        let asts = ["ab", "b$", "c", "^a", "x*"].map(|pattern| parse(pattern).unwrap());
+        let asts: Vec<_> = asts.iter().enumerate().collect();
+        let program = crate::compile::compile_set(&asts).unwrap();
+        let mut dfa = Dfa::new(&program).unwrap();
+        let mut matched = [false; 5];
+        assert_eq!(dfa.which_match(&program, "xab", &mut matched), Some(()));
+        assert_eq!(matched, [true, true, false, false, true]);
+        let mut matched = [false; 5];
+        assert_eq!(dfa.which_match(&program, "", &mut matched), Some(()));
+        assert_eq!(matched, [false, false, false, false, true]);
+    }
+
+    #[test]
+    fn match_word_assertions() {
+        let patterns = ["\\bab\\b", "\\Bb", "\\<a", "b\\>", "^\\bx", "a\\b$", "\\b", "\\B", "(a|\\b)+b", "é\\b"];
+        for pattern in patterns {
+            for unicode in [false, true] {
+                let mut ast = crate::parser::parse_with(pattern, crate::parser::Flags { unicode, ..Default::default() }).unwrap();
+                for whole_word in [false, true] {
+                    if whole_word {
+                        ast.surround(Look::WordStartHalf { unicode }, Look::WordEndHalf { unicode });
+                    }
+                    let program = compile(&ast).unwrap();
+                    let mut dfa = Dfa::new(&program).unwrap();
+                    for input in ["ab", "xab b", "cab", "a", "", " ", "é ab", "aé", "x", "éb", "b é"] {
+                        let expected = crate::pikevm::find_at(&program, input, 0).is_some();
+                        assert_eq!(dfa.is_match(&program, input), Some(expected), "{} on {:?}", pattern, input);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn match_whole_words_of_a_set() {
+        let mut asts = ["ab", "b", "a+"].map(|pattern| parse(pattern).unwrap());
+        for ast in &mut asts {
+            ast.surround(Look::WordStartHalf { unicode: false }, Look::WordEndHalf { unicode: false });
+        }
+        let asts: Vec<_> = asts.iter().enumerate().collect();
+        let program = crate::compile::compile_set(&asts).unwrap();
+        let mut dfa = Dfa::new(&program).unwrap();
+        let mut matched = [false; 3];
+        assert_eq!(dfa.which_match(&program, "cab b", &mut matched), Some(()));
+        assert_eq!(matched, [false, true, false]);
+        let mut matched = [false; 3];
+        assert_eq!(dfa.which_match(&program, "aa-ab", &mut matched), Some(()));
+        assert_eq!(matched, [true, false, true]);
+    }
+
+    #[test]
+    fn reuse_cached_states() {
+        let program = compile(&parse("a[bc]+d").unwrap()).unwrap();
+        let mut dfa = Dfa::new(&program).unwrap();
+        assert_eq!(dfa.is_match(&program, "xabcbcd"), Some(true));
+        let states = dfa.states.len();
+        assert_eq!(dfa.is_match(&program, "abcbcbd"), Some(true));
+        assert_eq!(dfa.states.len(), states);
+    }
+
+    #[test]
+    fn clear_cache_when_full() {
+        let program = compile(&parse("a.{12}b").unwrap()).unwrap();
+        let mut dfa = Dfa::new(&program).unwrap();
+        let input: String = (0..20_000).map(|i| if i % 3 == 0 { 'a' } else { 'x' }).collect();
+        assert_eq!(dfa.is_match(&program, &input), Some(false));
+        assert_eq!(dfa.is_match(&program, &random_line(20_000, 1)), Some(false));
+        assert!(dfa.clears > 0);
+        assert_eq!(dfa.is_match(&program, "xaxxxxxxxxxxxxb"), Some(true));
+    }
+
+    #[test]
+    fn give_up_when_cache_is_cleared_too_often() {
+        let program = compile(&parse("a.{16}b").unwrap()).unwrap();
+        let mut dfa = Dfa::new(&program).unwrap();
+        assert_eq!(dfa.is_match(&program, &random_line(50_000, 1)), None);
+        assert!(dfa.frozen);
+    }
+
+    /// Line of `a` and `x` in random order
+    fn random_line(len: usize, mut seed: u32) -> String {
+        (0..len)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                if seed.is_multiple_of(2) { 'a' } else { 'x' }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,64 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    UnclosedGroup,
+    UnopenedGroup,
+    UnclosedClass,
+    InvalidClassRange,
+    UnclosedRepetition,
+    InvalidRepetition,
+    RepetitionTooLarge,
+    RepetitionMissing,
+    ProgramTooLarge,
+    TrailingEscape,
+    UnsupportedEscape,
+    InvalidEscape,
+    UnknownProperty,
+    UnknownClass,
+    InvalidBackreference,
+}
+
+/// Error raised while parsing a pattern, `offset` is the byte offset of the offending token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternError {
+    pub kind: ErrorKind,
+    pub offset: usize,
+    pub message: String,
+    pub pattern: String,
+}
+
+impl PatternError {
+    pub fn new(kind: ErrorKind, offset: usize, message: &str, pattern: &str) -> Self {
+        PatternError {
+            kind,
+            offset,
+            message: message.to_string(),
+            pattern: pattern.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let column = self.pattern[..self.offset].chars().count();
+        writeln!(f, "regex parse error:")?;
+        writeln!(f, "    {}", self.pattern)?;
+        writeln!(f, "    {}^", " ".repeat(column))?;
+        write!(f, "error: {}", self.message)
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_caret_under_offset() {
+        let error = PatternError::new(ErrorKind::UnclosedGroup, 4, "unclosed group", "ab\u{00e9}(c");
+        assert_eq!(error.to_string(), "regex parse error:\n    ab\u{00e9}(c\n       ^\nerror: unclosed group");
+    }
+}
@@ -0,0 +1,47 @@
+//! Regular expression engine behind `rgrep`
+//!
+//! ```
+//! use rgrep::Regex;
+//!
+//! let regex = Regex::new("(\\w+) and \\1").unwrap();
+//! let captures = regex.captures("rust is great and great is scala").unwrap();
+//! assert_eq!(captures.get(0), Some("great and great"));
+//! assert_eq!(captures.get(1), Some("great"));
+//! ```
+//!
+//! Many patterns are searched in one pass with a [`RegexSet`]:
+//!
+//! ```
+//! use rgrep::RegexSet;
+//!
+//! let set = RegexSet::new(["\\d+", "error", "^GET"]).unwrap();
+//! assert_eq!(set.matches("GET /error"), vec![1, 2]);
+//! ```
+
+mod backtrack;
+mod class;
+mod compile;
+mod dfa;
+mod error;
+mod literal;
+mod parser;
+mod pikevm;
+mod regex;
+mod set;
+mod unicode_tables;
+
+pub use error::{ErrorKind, PatternError};
+pub use regex::{Captures, Match, Matches, Regex, RegexBuilder};
+pub use set::{RegexSet, RegexSetBuilder};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn share_between_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Regex>();
+        assert_send_sync::<RegexSet>();
+    }
+}
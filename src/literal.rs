@@ -0,0 +1,234 @@
+use crate::class::ClassSet;
+use crate::parser::{Ast, Node};
+use aho_corasick::AhoCorasick;
+use memchr::memmem;
+
+/// Largest number of alternative literals worth scanning for
+const MAX_LITERALS: usize = 16;
+
+/// Substring search for literals that every match has to contain
+pub enum Prefilter {
+    Single(Box<memmem::Finder<'static>>),
+    Set(AhoCorasick),
+}
+
+impl Prefilter {
+    pub fn new(ast: &Ast) -> Option<Prefilter> {
+        Prefilter::from_literals(required_literals(&ast.root, ast.flags.case_insensitive)?, ast.flags.case_insensitive)
+    }
+
+    /// Searches for the required literals of several patterns at once, `None` unless every
+    /// pattern has some
+    pub fn for_patterns<'a>(asts: impl IntoIterator<Item = &'a Ast>) -> Option<Prefilter> {
+        let mut literals = Vec::new();
+        let mut case_insensitive = false;
+        for ast in asts {
+            literals.extend(required_literals(&ast.root, ast.flags.case_insensitive)?);
+            case_insensitive |= ast.flags.case_insensitive;
+        }
+        if literals.is_empty() {
+            return None;
+        }
+        literals.sort();
+        literals.dedup();
+        Prefilter::from_literals(literals, case_insensitive)
+    }
+
+    /// Searches for any of `literals`, ignoring the case of ASCII letters when `case_insensitive`
+    pub fn from_literals(literals: Vec<String>, case_insensitive: bool) -> Option<Prefilter> {
+        match literals.as_slice() {
+            [literal] if !case_insensitive => {
+                Some(Prefilter::Single(Box::new(memmem::Finder::new(literal.as_bytes()).into_owned())))
+            }
+            _ => AhoCorasick::builder()
+                .ascii_case_insensitive(case_insensitive)
+                .build(&literals)
+                .ok()
+                .map(Prefilter::Set),
+        }
+    }
+
+    /// Returns false only when no match can start at or after `start`
+    pub fn is_candidate(&self, input: &str, start: usize) -> bool {
+        self.find(input, start).is_some()
+    }
+
+    /// Span of the first literal occurring at or after `start`
+    pub fn find(&self, input: &str, start: usize) -> Option<(usize, usize)> {
+        let haystack = &input.as_bytes()[start..];
+        match self {
+            Prefilter::Single(finder) => finder.find(haystack).map(|at| (start + at, start + at + finder.needle().len())),
+            Prefilter::Set(searcher) => searcher.find(haystack).map(|m| (start + m.start(), start + m.end())),
+        }
+    }
+}
+
+/// Text a pattern matches exactly, in lowercase for a case-insensitive pattern
+#[derive(Debug, PartialEq)]
+pub struct PureLiteral {
+    pub text: String,
+    /// Case folding adds non-ASCII chars to some ASCII letters, like the Kelvin sign to `k`,
+    /// so ASCII case-insensitive search for `text` is exact only on ASCII input
+    pub folds_non_ascii: bool,
+}
+
+/// Finds the text of a pattern made only of literal chars, which may be case folded ASCII letters
+/// when the pattern ignores case
+pub fn pure_literal(ast: &Ast) -> Option<PureLiteral> {
+    fn push(node: &Node, case_insensitive: bool, literal: &mut PureLiteral) -> Option<()> {
+        match node {
+            Node::Empty => {}
+            Node::Literal(c) => literal.text.push(*c),
+            Node::Class(set) if case_insensitive => {
+                let (c, folds_non_ascii) = folded_ascii_letter(set)?;
+                literal.text.push(c);
+                literal.folds_non_ascii |= folds_non_ascii;
+            }
+            Node::Group { node, .. } => push(node, case_insensitive, literal)?,
+            Node::Concat(nodes) => nodes.iter().try_for_each(|node| push(node, case_insensitive, literal))?,
+            _ => return None,
+        }
+        Some(())
+    }
+    let mut literal = PureLiteral { text: String::new(), folds_non_ascii: false };
+    push(&ast.root, ast.flags.case_insensitive, &mut literal)?;
+    Some(literal)
+}
+
+/// Picks the longest set of literals one of which occurs in every match of `node`, classes of
+/// an ASCII letter in both cases only count as that letter when the literals are searched
+/// `case_insensitive`
+fn required_literals(node: &Node, case_insensitive: bool) -> Option<Vec<String>> {
+    match node {
+        Node::Literal(c) => Some(vec![c.to_string()]),
+        Node::Group { node, .. } => required_literals(node, case_insensitive),
+        Node::Repeat { node, min, .. } if *min > 0 => required_literals(node, case_insensitive),
+        Node::Concat(nodes) => {
+            let mut candidates = Vec::new();
+            let mut run = String::new();
+            for node in nodes {
+                match node {
+                    Node::Literal(c) => run.push(*c),
+                    Node::Class(set) if case_insensitive && ascii_case_pair(set).is_some() => {
+                        run.extend(ascii_case_pair(set))
+                    }
+                    _ => {
+                        if !run.is_empty() {
+                            candidates.push(vec![std::mem::take(&mut run)]);
+                        }
+                        candidates.extend(required_literals(node, case_insensitive));
+                    }
+                }
+            }
+            if !run.is_empty() {
+                candidates.push(vec![run]);
+            }
+            candidates.into_iter().max_by_key(|literals| shortest(literals))
+        }
+        Node::Alternation(branches) => {
+            let mut literals = Vec::new();
+            for branch in branches {
+                literals.extend(required_literals(branch, case_insensitive)?);
+            }
+            literals.sort();
+            literals.dedup();
+            (literals.len() <= MAX_LITERALS).then_some(literals)
+        }
+        _ => None,
+    }
+}
+
+/// Lowercase letter of a class holding just an ASCII letter in both cases, as case folding produces
+fn ascii_case_pair(set: &ClassSet) -> Option<char> {
+    match set.ranges() {
+        [(upper, _), (lower, _)] if set.len() == 2 && upper.is_ascii_uppercase() && upper.to_ascii_lowercase() == *lower => {
+            Some(*lower)
+        }
+        _ => None,
+    }
+}
+
+/// Like [`ascii_case_pair`] but also allows the non-ASCII chars that fold to the letter,
+/// and tells whether there were any
+fn folded_ascii_letter(set: &ClassSet) -> Option<(char, bool)> {
+    match set.ranges() {
+        [(upper, upper_end), (lower, lower_end), rest @ ..]
+            if upper == upper_end
+                && lower == lower_end
+                && upper.is_ascii_uppercase()
+                && upper.to_ascii_lowercase() == *lower
+                && rest.iter().all(|(start, _)| !start.is_ascii()) =>
+        {
+            Some((*lower, !rest.is_empty()))
+        }
+        _ => None,
+    }
+}
+
+fn shortest(literals: &[String]) -> usize {
+    literals.iter().map(String::len).min().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn literals(pattern: &str) -> Option<Vec<String>> {
+        required_literals(&parse(pattern).unwrap().root, false)
+    }
+
+    #[test]
+    fn extract_prefix_and_inner_literals() {
+        assert_eq!(literals("rust"), Some(vec!["rust".to_string()]));
+        assert_eq!(literals("ERROR \\d+ timeout"), Some(vec![" timeout".to_string()]));
+        assert_eq!(literals("\\d+ (ab)+c"), Some(vec!["ab".to_string()]));
+        assert_eq!(literals("a?b*"), None);
+        assert_eq!(literals("\\d\\w"), None);
+    }
+
+    #[test]
+    fn extract_alternation_sets() {
+        assert_eq!(literals("(rust|scala)\\d"), Some(vec!["rust".to_string(), "scala".to_string()]));
+        assert_eq!(literals("rust|\\d"), None);
+    }
+
+    #[test]
+    fn extract_case_folded_literals() {
+        let ast = crate::parser::parse_with("error \\d", crate::parser::Flags { case_insensitive: true, ..Default::default() }).unwrap();
+        assert_eq!(required_literals(&ast.root, true), Some(vec!["error ".to_string()]));
+        let prefilter = Prefilter::new(&ast).unwrap();
+        assert!(prefilter.is_candidate("An ERROR 1", 0));
+        assert!(!prefilter.is_candidate("An ERR 1", 0));
+        let ast = crate::parser::parse_with("risk", crate::parser::Flags { case_insensitive: true, ..Default::default() }).unwrap();
+        assert_eq!(required_literals(&ast.root, true), Some(vec!["ri".to_string()]));
+        // a class written by hand does not make a case-sensitive search ignore case
+        assert_eq!(literals("[Ee]rror \\d"), Some(vec!["rror ".to_string()]));
+    }
+
+    #[test]
+    fn detect_pure_literals() {
+        let text = |pattern| pure_literal(&parse(pattern).unwrap()).map(|literal| literal.text);
+        assert_eq!(text("rust(ace)"), Some("rustace".to_string()));
+        assert_eq!(text(""), Some(String::new()));
+        assert_eq!(text("rust?"), None);
+        assert_eq!(text("[ab]"), None);
+        assert_eq!(text("[Aa]b"), None);
+        let ast = crate::parser::parse_with("Rum 2", crate::parser::Flags { case_insensitive: true, ..Default::default() }).unwrap();
+        assert_eq!(pure_literal(&ast), Some(PureLiteral { text: "rum 2".to_string(), folds_non_ascii: false }));
+        // the Kelvin sign folds to k as well
+        let ast = crate::parser::parse_with("Ok", crate::parser::Flags { case_insensitive: true, ..Default::default() }).unwrap();
+        assert_eq!(pure_literal(&ast), Some(PureLiteral { text: "ok".to_string(), folds_non_ascii: true }));
+        let ast = crate::parser::parse_with("é", crate::parser::Flags { case_insensitive: true, ..Default::default() }).unwrap();
+        assert_eq!(pure_literal(&ast), None);
+    }
+
+    #[test]
+    fn filter_candidates() {
+        let prefilter = Prefilter::new(&parse("^\\w+ (ERROR|WARN)").unwrap()).unwrap();
+        assert!(prefilter.is_candidate("db WARN slow", 0));
+        assert!(!prefilter.is_candidate("db WARN slow", 4));
+        assert!(!prefilter.is_candidate("db INFO slow", 0));
+        assert_eq!(prefilter.find("db WARN slow", 0), Some((3, 7)));
+    }
+}
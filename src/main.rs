@@ -1,8 +1,10 @@
 use clap::{Parser, ValueEnum};
-use std::collections::HashMap;
+use rgrep::{Match, RegexSet, RegexSetBuilder};
+use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::fs::File;
 use std::io;
-use std::io::{stdout, BufRead, BufReader, IsTerminal};
+use std::io::{stdout, BufRead, BufReader, BufWriter, IsTerminal, Write};
 use std::process;
 use std::fs;
 
@@ -29,344 +31,426 @@ struct Args {
     #[arg(short = 'r')]
     recursive: bool,
 
+    /// Ignore case distinctions in patterns and input
+    #[arg(short = 'i', long)]
+    ignore_case: bool,
+
+    /// Ignore case unless the pattern contains uppercase characters
+    #[arg(short = 'S', long)]
+    smart_case: bool,
+
+    /// Take patterns as plain strings instead of regular expressions
+    #[arg(short = 'F', long)]
+    fixed_strings: bool,
+
+    /// Select only matches that are whole words, not next to a letter, digit or underscore
+    #[arg(short = 'w', long)]
+    word_regexp: bool,
+
+    /// Select only matches that span the whole line
+    #[arg(short = 'x', long)]
+    line_regexp: bool,
+
+    /// Match \d, \s, \w and word boundaries by their Unicode definitions instead of ASCII ones
+    #[arg(long)]
+    unicode: bool,
+
+    /// Select non-matching lines
+    #[arg(short = 'v', long)]
+    invert_match: bool,
+
+    /// Prefix each line with its 1-based line number
+    #[arg(short = 'n', long)]
+    line_number: bool,
+
+    /// Prefix each line with the 1-based byte column of its first match
+    #[arg(long)]
+    column: bool,
+
+    /// Prefix each line, or each match with -o, with its 0-based byte offset
+    #[arg(short = 'b', long)]
+    byte_offset: bool,
+
+    /// Print NUM lines of trailing context after matching lines
+    #[arg(short = 'A', long, value_name = "NUM")]
+    after_context: Option<usize>,
+
+    /// Print NUM lines of leading context before matching lines
+    #[arg(short = 'B', long, value_name = "NUM")]
+    before_context: Option<usize>,
+
+    /// Print NUM lines of context around matching lines
+    #[arg(short = 'C', long, value_name = "NUM")]
+    context: Option<usize>,
+
+    /// Print the number of selected lines per file instead of the lines
+    #[arg(short = 'c', long)]
+    count: bool,
+
+    /// Print the number of matches per file, counting every match on a line
+    #[arg(long)]
+    count_matches: bool,
+
+    /// Print only the names of files with a selected line
+    #[arg(short = 'l', long)]
+    files_with_matches: bool,
+
+    /// Print only the names of files without a selected line
+    #[arg(short = 'L', long)]
+    files_without_match: bool,
+
+    /// Print nothing and exit with status 0 on the first selected line
+    #[arg(short = 'q', long)]
+    quiet: bool,
+
+    /// Stop reading a file after NUM selected lines
+    #[arg(short = 'm', long, value_name = "NUM")]
+    max_count: Option<usize>,
+
+    /// Flush output after every line, even when writing to a file
+    #[arg(long)]
+    line_buffered: bool,
+
     #[arg(
         short = 'E',
         value_name = "PATTERN",
-        required = true,
+        required_unless_present_any = ["regexp", "pattern_files"],
     )]
-    pattern: String,
+    pattern: Option<String>,
+
+    /// Search for PATTERN too, can be given several times
+    #[arg(short = 'e', long, value_name = "PATTERN")]
+    regexp: Vec<String>,
+
+    /// Search for the patterns in FILE, one per line
+    #[arg(short = 'f', long = "file", value_name = "FILE")]
+    pattern_files: Vec<String>,
 
     #[arg(value_name = "FILE")]
     files: Vec<String>,
 }
 
-pub struct Stack<T> {
-    items: Vec<T>,
-}
+/// Colors of matches, picked by the index of the pattern they came from
+const MATCH_COLORS: [&str; 6] = ["01;31", "01;32", "01;33", "01;34", "01;35", "01;36"];
 
-impl<T> Stack<T> {
-    pub fn new() -> Self {
-        Stack {
-            items: Vec::new(),
+/// Non-empty matches of all patterns from left to right, each with the index of its pattern. Only the
+/// patterns the set reports as matching are searched. Where matches start at the same position the earlier pattern wins, and a match
+/// overlapping a previous one is looked for again after it.
+fn match_re(input: &str, set: &RegexSet) -> Vec<(usize, Match)> {
+    let regexes = set.regexes();
+    let mut matches = Vec::new();
+    let mut next: Vec<(usize, Option<Match>)> = set
+        .matches(input)
+        .into_iter()
+        .map(|pattern| (pattern, regexes[pattern].find_non_empty_at(input, 0)))
+        .collect();
+    let mut pos = 0;
+    loop {
+        for (pattern, candidate) in next.iter_mut() {
+            if candidate.as_ref().is_some_and(|m| m.start < pos) {
+                *candidate = regexes[*pattern].find_non_empty_at(input, pos);
+            }
         }
+        let first = next
+            .iter_mut()
+            .filter(|(_, candidate)| candidate.is_some())
+            .min_by_key(|(pattern, candidate)| (candidate.as_ref().map(|m| m.start), *pattern));
+        let Some((pattern, candidate)) = first else {
+            break;
+        };
+        let Some(m) = candidate.take() else {
+            break;
+        };
+        pos = m.end;
+        *candidate = regexes[*pattern].find_non_empty_at(input, pos);
+        matches.push((*pattern, m));
     }
+    matches
+}
 
-    pub fn push(&mut self, item: T) {
-        self.items.push(item);
-    }
-
-    pub fn pop(&mut self) -> Option<T> {
-        self.items.pop()
-    }
+#[derive(Debug, PartialEq)]
+struct Line {
+    number: usize,
+    offset: usize,
+    text: String,
+    /// Matches with the index of the pattern they came from
+    matches: Vec<(usize, Match)>,
+    /// Where a `�` in `text` replaced invalid UTF-8, with the number of bytes it replaced
+    replaced: Vec<(usize, usize)>,
+    /// Printed around a selected line without being selected itself
+    context: bool,
+}
 
-    pub fn is_empty(&self) -> bool {
-        self.items.is_empty()
+impl Line {
+    /// Byte offset in the raw line of `pos` in `text`
+    fn raw_offset(&self, pos: usize) -> usize {
+        self.replaced
+            .iter()
+            .take_while(|(at, _)| *at < pos)
+            .fold(pos, |pos, (_, len)| pos + len - char::REPLACEMENT_CHARACTER.len_utf8())
     }
 }
 
-fn find_matching_paren(input: &str) -> usize {
-    let mut stack = Stack::new();
-    stack.push("(");
-    for (i, c) in input.chars().skip(1).enumerate() {
-        if c == ')' {
-            stack.pop();
-        } else if c == '(' {
-            stack.push("(");
-        }
-        if stack.is_empty() {
-            return i + 1;
+/// Offsets of the `�` that `String::from_utf8_lossy` puts in place of invalid sequences, with their lengths
+fn replacements(bytes: &[u8]) -> Vec<(usize, usize)> {
+    let mut replaced = Vec::new();
+    let mut pos = 0;
+    for chunk in bytes.utf8_chunks() {
+        pos += chunk.valid().len();
+        if !chunk.invalid().is_empty() {
+            replaced.push((pos, chunk.invalid().len()));
+            pos += char::REPLACEMENT_CHARACTER.len_utf8();
         }
     }
-    0
+    replaced
 }
 
-fn extract_pattern(pattern_block: &str) -> &str {
-    if pattern_block.chars().count() > 1 && (pattern_block.starts_with("\\d") || pattern_block.starts_with("\\w"))  {
-        &pattern_block[..2]
-    } else if pattern_block.starts_with("[") && pattern_block.chars().count() > 1 {
-        let len = pattern_block.chars().take_while(|x| *x != ']').count()+1;
-        &pattern_block[..len]
-    } else if pattern_block.starts_with("\\") && pattern_block.chars().count() > 1 {
-        let len = pattern_block.chars().skip(1).take_while(|x| x.is_ascii_digit() ).count()+1;
-        &pattern_block[..len]
-    } else if pattern_block.starts_with("(") && pattern_block.chars().count() > 1 {
-        let len = find_matching_paren(pattern_block) + 1;
-        &pattern_block[..len]
-    } else if pattern_block.starts_with("{") && pattern_block.chars().count() > 1 {
-        let len = pattern_block.chars().take_while(|x| *x != '}').count()+1;
-        &pattern_block[..len]
-    } else if pattern_block.chars().count() > 0 {
-        &pattern_block[..1]
-    } else {
-        pattern_block
-    }
+/// Which lines `process_lines` passes on
+#[derive(Debug, Default)]
+struct Options {
+    invert: bool,
+    before_context: usize,
+    after_context: usize,
+    /// Stops reading once this many lines were selected and their trailing context passed on
+    max_count: Option<usize>,
+    /// Finds the matches of selected lines, only -o, color, --column and --count-matches use them
+    need_matches: bool,
 }
 
-fn extract_quantifier(pattern: &str, skip: usize) -> (Option<String>, Option<usize>, Option<usize>) {
-    match pattern.chars().skip(skip).next() {
-        Some('+') => (Some("+".to_string()), None, None),
-        Some('*') => (Some("*".to_string()), None, None),
-        Some('?') => (Some("?".to_string()), None, None),
-        Some(c) if c == '{' => {
-            let q = pattern.chars().skip(skip+1).take_while(|x| *x != '}').collect::<String>();
-            match q.split_once(',') {
-                Some((left, right)) => (Some(format!("{{{}}}", q)), left.parse::<usize>().ok(), right.parse::<usize>().ok()),
-                None => (Some(format!("{{{}}}", q)), q.parse::<usize>().ok(), q.parse::<usize>().ok())
-            }
+/// Passes on the selected lines, matching ones or with `invert` the non-matching ones, together with
+/// the matches to print when `need_matches`, which selected non-matching lines never have. Context lines around them are
+/// passed on too, only the last `before_context` lines are kept so the input is streamed.
+/// Returns whether any line was selected.
+fn process_lines<R, F>(mut reader: R, set: &RegexSet, options: &Options, mut emit: F) -> io::Result<bool>
+where
+    R: BufRead,
+    F: FnMut(Line) -> io::Result<()>,
+{
+    let mut before = VecDeque::with_capacity(options.before_context);
+    let mut after = 0;
+    let mut selected = 0;
+    let mut buffer = Vec::new();
+    let mut offset = 0;
+    for number in 1.. {
+        let limit_reached = options.max_count.is_some_and(|max| selected >= max);
+        if limit_reached && after == 0 {
+            break;
         }
-        _ => (None, None, None)
-    }
-}
-
-fn match_re(input: &str, pattern: &str) -> Vec<String> {
-    let mut matches = Vec::new();
-    if pattern.starts_with('^') {
-        let captured: HashMap<usize, String> = HashMap::new();
-        let (matched,  consumed) = match_block(input, &pattern[1..], 0, &mut Some(captured));
-        if matched {
-            let matched_input = input.chars().take(consumed).collect();
-            matches.push(matched_input);
+        buffer.clear();
+        let read = reader.read_until(b'\n', &mut buffer)?;
+        if read == 0 {
+            break;
         }
-        matches
-    } else {
-        for (i, _) in input.chars().enumerate() {
-            let skipped_input = input.chars().skip(i).collect::<String>();
-            let captured: HashMap<usize, String> = HashMap::new();
-            let (matched, consumed) = match_block(&skipped_input.as_str(), pattern, 0, &mut Some(captured));
-            if matched {
-                let matched_input = skipped_input.chars().take(consumed).collect();
-                matches.push(matched_input);
-                matches.extend(match_re(&skipped_input.chars().skip(consumed).collect::<String>(), pattern));
-                return matches
+        // invalid UTF-8 is replaced rather than ending the search, offsets still count the raw bytes
+        // and `Line::raw_offset` maps match offsets back to them
+        let line = String::from_utf8_lossy(&buffer);
+        let text = line.strip_suffix('\n').unwrap_or(&line);
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        if !limit_reached && set.is_match(text) != options.invert {
+            selected += 1;
+            before.drain(..).try_for_each(&mut emit)?;
+            let matches = if options.need_matches && !options.invert { match_re(text, set) } else { Vec::new() };
+            let replaced = if matches!(line, Cow::Owned(_)) { replacements(&buffer) } else { Vec::new() };
+            emit(Line { number, offset, text: text.to_string(), matches, replaced, context: false })?;
+            after = options.after_context;
+        } else if after > 0 {
+            after -= 1;
+            emit(Line { number, offset, text: text.to_string(), matches: Vec::new(), replaced: Vec::new(), context: true })?;
+        } else if options.before_context > 0 {
+            if before.len() == options.before_context {
+                before.pop_front();
             }
+            before.push_back(Line { number, offset, text: text.to_string(), matches: Vec::new(), replaced: Vec::new(), context: true });
         }
-        vec![]
+        offset += read;
     }
+    Ok(selected > 0)
 }
 
-fn match_pattern(input: &str, pattern: &str, captured: &mut Option<HashMap<usize, String>>, pattern_ahead: Option<&str>) -> (bool, usize) {
-    if input.len() == 0 {
-        (false, 0)
-    } else if pattern == "." {
-        (true, 1)
-    } else if pattern.chars().count() == 1 {
-        (input.starts_with(pattern), 1)
-    } else if pattern == "\\d" {
-        (input.chars().next().map_or(false, |x| x.is_ascii_digit()), 1)
-    } else if pattern == "\\w" {
-        (input.chars().next().map_or(false, |x| x.is_ascii_alphabetic() || x.is_ascii_digit() || x == '_'), 1)
-    } else if pattern.starts_with("\\") {
-        let backreference = pattern.trim_matches(|c| c == '\\').parse::<usize>().ok().map(|r| captured.as_ref().map(|x| x.get(&r)).flatten()).flatten();
-        (backreference.map(|x| input.starts_with(x)).unwrap_or(false), backreference.map(|x| x.len()).unwrap_or(0))
-    } else if pattern.starts_with('(') && pattern.ends_with(')') && (!pattern.contains('|') || pattern[1..pattern.len() - 1].contains("(")) {
-        match_grouping(input, pattern, &pattern_ahead, captured, true)
-    } else if pattern.starts_with("[^") && pattern.ends_with(']') {
-        match_any_except(input, &pattern[2..pattern.len() - 1], 1)
-    } else if pattern.starts_with('[') && pattern.ends_with(']') {
-        match_any(input, &pattern[1..pattern.len() - 1], 0)
-    } else if pattern.starts_with('(') && pattern.ends_with(')') && !pattern[1..pattern.len() - 1].contains("(") {
-        let (matched, consumed) = match_or(input, &pattern[1..pattern.len() - 1]);
-        let key = captured.as_ref().map_or(0, |x| x.len() + 1);
-        captured.as_mut().map(|x| x.insert(key, input.chars().take(consumed).collect::<String>().to_string()));
-        (matched, consumed)
-    } else {
-        panic!("Unhandled pattern: {}", pattern)
+/// Builds the `file:line:column:offset:` prefix, `m` is the match printed on its own with -o.
+/// Context lines use `-` instead of `:` like GNU grep.
+fn line_prefix(args: &Args, filename: Option<&str>, line: &Line, m: Option<&Match>) -> String {
+    let marker = if line.context { '-' } else { ':' };
+    let mut prefix = String::new();
+    if let Some(filename) = filename {
+        prefix.push_str(&format!("{}{}", filename, marker));
     }
-}
-
-fn match_grouping(input: &str, pattern: &str, pattern_ahead: &Option<&str>, captured: &mut Option<HashMap<usize, String>>, inc: bool) -> (bool, usize) {
-    let key = captured.as_ref().map_or(0, |x| x.len() + 1);
-    if inc {
-        captured.as_mut().map(|x| x.insert(key, "".to_string()));
-    }
-    let (mut matched, mut consumed) = match_block(input, &pattern[1..pattern.len() - 1], 0, captured);
-    captured.as_mut().map(|x| x.insert(key, input.chars().take(consumed).collect::<String>().to_string()));
-    if inc {
-        let mut cap = captured.clone();
-        let x = consumed;
-        for i in 0..x {
-            if !match_block(input.chars().skip(x - i).collect::<String>().as_str(), pattern_ahead.unwrap_or(""), 0, &mut cap).0 {
-                (matched, consumed) = match_grouping(input.chars().take(x - i -1).collect::<String>().as_str(), pattern, pattern_ahead, &mut cap, false);
-            } else {
-                break;
-            }
-        }
-        captured.as_mut().map(|x| x.insert(key, input.chars().take(consumed).collect::<String>().to_string()));
+    if args.line_number {
+        prefix.push_str(&format!("{}{}", line.number, marker));
+    }
+    if args.column
+        && let Some(first) = m.or(line.matches.first().map(|(_, m)| m))
+    {
+        prefix.push_str(&format!("{}{}", line.raw_offset(first.start) + 1, marker));
+    }
+    if args.byte_offset {
+        prefix.push_str(&format!("{}{}", line.offset + m.map_or(0, |m| line.raw_offset(m.start)), marker));
     }
-    (matched, consumed)
+    prefix
 }
 
-fn match_or(input: &str, patterns: &str) -> (bool, usize) {
-    patterns
-        .split_terminator('|')
-        .map(|pattern| match_pattern(input, format!("({})", pattern).as_str(), &mut None, None))
-        .find(|x| x.0)
-        .unwrap_or((false, 0))
+/// Prints lines as they are passed on, separating groups of lines that are not adjacent with `--`.
+/// With `line_buffered` every line is flushed right away instead of when the buffer fills up.
+struct Printer<'a, W: Write> {
+    args: &'a Args,
+    out: W,
+    colored: bool,
+    separated: bool,
+    show_filename: bool,
+    line_buffered: bool,
+    printed: bool,
+    last_number: Option<usize>,
+    /// Set once an input could not be read, its error goes to stderr and the search goes on
+    failed: bool,
+    /// Set once a line was selected, tells the exit status when the reader goes away
+    selected: bool,
 }
 
-fn match_any(input: &str, patterns: &str, skip: usize) -> (bool, usize) {
-    if patterns.is_empty() {
-        (false, 0)
-    } else {
-        let pattern = extract_pattern(patterns);
-        let (matched, consumed) = match_pattern(input, pattern, &mut None, None);
-        if !matched {
-            match_any(input, &patterns[skip..], pattern.len())
-        } else {
-            (matched, consumed)
-        }
+impl<'a, W: Write> Printer<'a, W> {
+    fn new(args: &'a Args, out: W, colored: bool, separated: bool, show_filename: bool, line_buffered: bool) -> Self {
+        Printer { args, out, colored, separated, show_filename, line_buffered, printed: false, last_number: None, failed: false, selected: false }
     }
-}
 
-fn match_any_except(input: &str, patterns: &str, skip: usize) -> (bool, usize) {
-    if patterns.is_empty() {
-        (true, 1)
-    } else {
-        let pattern = extract_pattern(patterns);
-        let (matched, _) = match_pattern(input, pattern, &mut None, None);
-        if matched {
-            (false, 0)
-        } else {
-            match_any_except(input, &patterns[skip..], pattern.len())
+    fn write_line(&mut self, prefix: &str, text: &str) -> io::Result<()> {
+        writeln!(self.out, "{}{}", prefix, text)?;
+        if self.line_buffered {
+            self.out.flush()?;
         }
+        Ok(())
     }
-}
 
-fn match_one_or_more(input: &str, pattern: &str, pattern_ahead: &str) -> (bool, usize) {
-    let (matched, consumed) = match_pattern(input, pattern, &mut None, None);
-    if matched {
-        match_n(input, pattern, pattern_ahead, consumed)
-    } else {
-        (false, 0)
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
     }
-}
 
-fn match_exactly(input: &str, pattern: &str, skip: usize, n: usize) -> (bool, usize) {
-    let (matched, consumed, matches) = consume(input, pattern, skip, 0, Some(n));
-    if matches < n {
-        (false, 0)
-    } else {
-        (matched, consumed)
+    fn print_count(&mut self, name: &str, count: usize) -> io::Result<()> {
+        let prefix = if self.show_filename { format!("{}:", name) } else { String::new() };
+        self.write_line(&prefix, &count.to_string())
     }
-}
 
-fn match_at_least(input: &str, pattern: &str, pattern_ahead: &str, skip: usize, n: usize) -> (bool, usize) {
-    let (matched, consumed, matches) = consume(input, pattern, skip, 0, None);
-    if matches < n {
-        (false, 0)
-    } else if matched && (pattern_ahead == "" || match_block(input.chars().skip(consumed).collect::<String>().as_str(), pattern_ahead, 0, &mut None).0) {
-        (matched, consumed)
-    } else {
-        let found = (matches-1..1)
-            .map(|x| consume(input, pattern, skip, 0, Some(x)))
-            .find(|(_, consumed, _)| match_block(input.chars().skip(*consumed).collect::<String>().as_str(), pattern_ahead, 0, &mut None).0);
-        match found {
-            Some((matched, consumed, matches)) if matches >= n => (matched, consumed),
-            _ => (false, 0),
-        }
+    fn print_filename(&mut self, name: &str) -> io::Result<()> {
+        self.write_line("", name)
     }
-}
 
-fn match_between(input: &str, pattern: &str, pattern_ahead: &str, skip: usize, n: usize, m: usize) -> (bool, usize) {
-    let (matched, consumed, matches) = consume(input, pattern, skip, 0, Some(m));
-    if matches < n {
-        (false, 0)
-    } else if matched && matches >= n && matches <= m && (pattern_ahead == "" || match_block(input.chars().skip(consumed).collect::<String>().as_str(), pattern_ahead, 0, &mut None).0) {
-        (matched, consumed)
-    } else {
-        let found = (m..1)
-            .map(|x| consume(input, pattern, skip, 0, Some(x)))
-            .find(|(_, consumed, _)| match_block(input.chars().skip(*consumed).collect::<String>().as_str(), pattern_ahead, 0, &mut None).0);
-        match found {
-            Some((matched, consumed, matches)) if matches >= n && matches <= m => (matched, consumed),
-            _ => (false, 0),
-        }
+    fn print_error(&mut self, name: &str, e: io::Error) {
+        eprintln!("rgrep: {}: {}", name, e);
+        self.failed = true;
     }
-}
 
-fn match_n(input: &str, pattern: &str, pattern_ahead: &str, skip: usize) -> (bool, usize) {
-    let (_, consumed, _) = consume(input, pattern, skip, 0, None);
-    let (_, consumed_backtracked) = backtrack(input.chars().skip(skip).collect::<String>().as_str(), pattern_ahead, consumed);
-    (true, skip + consumed_backtracked)
-}
+    fn start_file(&mut self) {
+        self.last_number = None;
+    }
 
-fn consume(input: &str, pattern: &str, skip: usize, matches: usize, n: Option<usize>) -> (bool, usize, usize) {
-    if n.map_or(false, |x| x == matches) || input == "" {
-        (true, 0, matches)
-    } else {
-        let (matched, consumed) = match_pattern(input.chars().skip(skip).collect::<String>().as_str(), pattern, &mut None, None);
-        if matched {
-            let (matched_rest, consumed_rest, matches_rest) = consume(input.chars().skip(skip).collect::<String>().as_str(), pattern, consumed, matches + 1, n);
-            if matched_rest {
-                (true, consumed + consumed_rest, matches_rest)
-            } else {
-                (true, consumed, matches_rest)
+    fn print(&mut self, name: &str, line: &Line) -> io::Result<()> {
+        let filename = self.show_filename.then_some(name);
+        // context lines are only passed on around a selected line
+        self.selected = true;
+        if self.separated && self.printed && self.last_number.is_none_or(|last| last + 1 != line.number) {
+            self.write_line("", "--")?;
+        }
+        self.printed = true;
+        self.last_number = Some(line.number);
+        if self.args.only_matching {
+            for (pattern, m) in &line.matches {
+                let text = &line.text[m.start..m.end];
+                let text = if self.colored { highlight(text, *pattern) } else { text.to_string() };
+                self.write_line(&line_prefix(self.args, filename, line, Some(m)), &text)?;
             }
+            Ok(())
+        } else if self.colored {
+            self.write_line(&line_prefix(self.args, filename, line, None), &highlight_matches(&line.text, &line.matches))
         } else {
-            (false, 0, matches)
+            self.write_line(&line_prefix(self.args, filename, line, None), &line.text)
         }
     }
 }
 
-fn backtrack(input: &str, pattern: &str, iter: usize) -> (bool, usize) {
-    if iter == 0 {
-        (true, 0)
-    } else {
-        let (matched, _) = match_block(input.chars().skip(iter).collect::<String>().as_str(), pattern, 0, &mut None);
-        if matched {
-            (true, iter)
-        } else {
-            backtrack(input, pattern, iter - 1)
-        }
-    }
+/// Counts a selected line once, or with --count-matches by its matches unless lines are inverted
+fn line_count(args: &Args, line: &Line) -> usize {
+    if args.count_matches && !args.invert_match { line.matches.len() } else { 1 }
 }
 
-fn match_one_or_none(input: &str, pattern: &str) -> (bool, usize) {
-    let (matched, _) = match_pattern(input, pattern, &mut None, None);
-    if matched {
-        (true, 1)
+/// Searches one input and prints its lines, their count or its name. Returns whether any line was
+/// selected, or with -L whether the name was printed.
+fn search_input<R: BufRead, W: Write>(
+    reader: R,
+    name: &str,
+    set: &RegexSet,
+    options: &Options,
+    printer: &mut Printer<W>,
+) -> io::Result<bool> {
+    let args = printer.args;
+    printer.start_file();
+    if args.quiet {
+        process_lines(reader, set, options, |_| Ok(()))
+    } else if args.files_with_matches || args.files_without_match {
+        let selected = process_lines(reader, set, options, |_| Ok(()))?;
+        printer.selected |= selected;
+        let listed = selected != args.files_without_match;
+        if listed {
+            printer.print_filename(name)?;
+        }
+        Ok(listed)
+    } else if args.count || args.count_matches {
+        let mut count = 0;
+        let selected = process_lines(reader, set, options, |line| {
+            count += line_count(args, &line);
+            Ok(())
+        })?;
+        printer.selected |= selected;
+        printer.print_count(name, count)?;
+        Ok(selected)
     } else {
-        (true, 0)
+        process_lines(reader, set, options, |line| printer.print(name, &line))
     }
 }
 
-fn match_block(input: &str, pattern: &str, skip: usize, mut captured: &mut Option<HashMap<usize, String>>) -> (bool, usize) {
-    if pattern == "$" {
-        (input.chars().skip(skip).count() == 0, 0)
-    } else if pattern.chars().count() > 0 && input.len() == 0 {
-        (false, 0)
-    } else if pattern.chars().count() > 0 {
-        let current_pattern = extract_pattern(pattern);
-        let quantifier = extract_quantifier(pattern, current_pattern.chars().count());
-        let quantifier_size = quantifier.0.as_ref().map_or(0, |x| x.len());
-
-        let match_input = input.chars().skip(skip).collect::<String>();
-        let (matched, consumed) = match quantifier {
-            (Some(q), _, _) if q == "+" => match_one_or_more(&match_input.as_str(), &current_pattern, &pattern[current_pattern.len() + quantifier_size ..]),
-            (Some(q), _, _) if q == "*" => match_n(&match_input.as_str(), &current_pattern, &pattern[current_pattern.len() + quantifier_size ..], 0),
-            (Some(q), _, _) if q == "?" => match_one_or_none(&match_input.as_str(), &current_pattern),
-            (Some(_), Some(n), Some(m)) if n == m => match_exactly(&match_input.as_str(), &current_pattern, 0, n),
-            (Some(_), Some(n), None) => match_at_least(&match_input.as_str(), &current_pattern, &pattern[current_pattern.len() + quantifier_size ..], 0, n),
-            (Some(_), Some(n), Some(m)) => match_between(&match_input.as_str(), &current_pattern, &pattern[current_pattern.len() + quantifier_size ..], 0, n, m),
-            _ => match_pattern(&match_input.as_str(), &current_pattern, &mut captured, Some(&pattern[current_pattern.len() + quantifier_size ..]))
+/// Searches stdin when no files are given, with -q only until the first selected line. Files that
+/// cannot be opened are reported and skipped like GNU grep does.
+fn search_files<W: Write>(
+    files: &[String],
+    set: &RegexSet,
+    options: &Options,
+    printer: &mut Printer<W>,
+) -> io::Result<bool> {
+    let args = printer.args;
+    if files.is_empty() && !args.recursive {
+        return search_input(BufReader::new(io::stdin().lock()), "(standard input)", set, options, printer);
+    }
+    let mut selected = false;
+    for name in files {
+        let file = File::open(name)
+            .and_then(|file| if file.metadata()?.is_dir() { Err(io::ErrorKind::IsADirectory.into()) } else { Ok(file) });
+        let file = match file {
+            Ok(file) => file,
+            Err(e) => {
+                printer.print_error(name, e);
+                continue;
+            }
         };
-
-        if matched {
-            let (matched_rest, consumed_rest) = match_block(input, &pattern[current_pattern.len() + quantifier_size ..], skip + consumed, &mut captured);
-            (matched && matched_rest, consumed + consumed_rest)
-        } else {
-            (false, 0)
+        let with_name = |e: io::Error| io::Error::new(e.kind(), format!("{}: {}", name, e));
+        selected |= search_input(BufReader::new(file), name, set, options, printer).map_err(with_name)?;
+        if args.quiet && selected {
+            break;
         }
-    } else {
-        (true, 0)
     }
+    Ok(selected)
 }
 
-fn process_lines<R: BufRead>(reader: R, pattern: &str) -> Vec<(String, Vec<String>)> {
-    reader.lines()
-        .filter_map(|line| line.ok())
-        .map(|line| (line.clone(), match_re(line.as_str(), pattern)))
-        .collect()
+/// Whether stdout is a pipe, where a reader like `tail -f log | rgrep` waits for every line
+#[cfg(unix)]
+fn is_pipe(stdout: &io::Stdout) -> bool {
+    use std::os::fd::AsFd;
+    use std::os::unix::fs::FileTypeExt;
+    stdout.as_fd()
+        .try_clone_to_owned()
+        .and_then(|fd| File::from(fd).metadata())
+        .is_ok_and(|metadata| metadata.file_type().is_fifo())
+}
+
+#[cfg(not(unix))]
+fn is_pipe(_stdout: &io::Stdout) -> bool {
+    false
 }
 
 fn collect_files(dir: String) -> Vec<String> {
@@ -388,26 +472,61 @@ fn collect_files(dir: String) -> Vec<String> {
     result
 }
 
-fn highlight_matches(line: &str, matches: &Vec<String>) -> String {
+fn highlight_matches(line: &str, matches: &[(usize, Match)]) -> String {
     let mut result = String::new();
-    let mut rest = line;
-    for m in matches {
-        if let Some((left, right)) = rest.split_once(m) {
-            rest = right;
-            result.push_str(&format!("{left}\x1b[01;31m{m}\x1b[m"));
-        }
+    let mut last = 0;
+    for (pattern, m) in matches {
+        result.push_str(&format!("{}{}", &line[last..m.start], highlight(&line[m.start..m.end], *pattern)));
+        last = m.end;
     }
-    result.push_str(rest);
+    result.push_str(&line[last..]);
     result
 }
 
+fn highlight(text: &str, pattern: usize) -> String {
+    format!("\x1b[{}m{}\x1b[m", MATCH_COLORS[pattern % MATCH_COLORS.len()], text)
+}
+
+/// Patterns from -E and every -e in order, followed by the lines of every -f file
+fn collect_patterns(args: &Args) -> io::Result<Vec<String>> {
+    let mut patterns: Vec<String> = args.pattern.iter().chain(&args.regexp).cloned().collect();
+    for name in &args.pattern_files {
+        let content = fs::read_to_string(name).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", name, e)))?;
+        patterns.extend(content.lines().map(str::to_string));
+    }
+    Ok(patterns)
+}
+
 fn main() -> io::Result<()> {
     let args = Args::parse();
 
+    let patterns = match collect_patterns(&args) {
+        Ok(patterns) => patterns,
+        Err(e) => {
+            eprintln!("rgrep: {}", e);
+            process::exit(2);
+        }
+    };
+    let set = match RegexSetBuilder::new(&patterns)
+        .case_insensitive(args.ignore_case)
+        .smart_case(args.smart_case)
+        .fixed_strings(args.fixed_strings)
+        .whole_word(args.word_regexp)
+        .whole_line(args.line_regexp)
+        .unicode(args.unicode)
+        .build()
+    {
+        Ok(set) => set,
+        Err(e) => {
+            eprintln!("rgrep: {}", e);
+            process::exit(2);
+        }
+    };
+
     let files: Vec<String> = if !args.recursive {
-        args.files
+        args.files.clone()
     } else {
-        match args.files.get(0) {
+        match args.files.first() {
             Some(path) => {
                 collect_files(path.clone())
             }
@@ -418,58 +537,43 @@ fn main() -> io::Result<()> {
         }
     };
 
-    let lines = if files.is_empty() && !args.recursive {
-        process_lines(BufReader::new(io::stdin().lock()), &args.pattern)
-    } else {
-        let mut result = Vec::new();
-        for filename in &files {
-            match File::open(&filename) {
-                Ok(file) => {
-                    let lines = process_lines(BufReader::new(file), &args.pattern);
-                    if !lines.is_empty() {
-                        result.extend(lines
-                            .into_iter()
-                            .map(|(l, m)| {
-                                let line = if files.len() > 1 || args.recursive {
-                                    format!("{}:{}", filename, l)
-                                } else {
-                                    format!("{}", l)
-                                };
-                                (line, m)
-                            })
-                            .collect::<Vec<(String, Vec<String>)>>()
-                        );
-                    }
-                }
-                Err(e) => {
-                    return Err(e);
-                }
-            }
-        }
-        result
+    let stdout = stdout();
+    let colored = match args.color {
+        Some(ArgColor::Always) => true,
+        Some(ArgColor::Auto) => stdout.is_terminal(),
+        _ => false,
     };
-
-    match lines.iter().find(|(_, matches)| !matches.is_empty()) {
-        Some(_) => {
-            if args.only_matching {
-                lines.iter()
-                    .filter(|(_, matches)| !matches.is_empty())
-                    .for_each(|(_, matches)| matches.iter()
-                        .for_each(|substr| println!("{}", substr)));
-            } else {
-                lines
-                    .iter()
-                    .filter(|(_, matches)| !matches.is_empty())
-                    .for_each(|(line, matches)| match args.color {
-                        Some(ArgColor::Always) => println!("{}", highlight_matches(line, matches)),
-                        Some(ArgColor::Auto) if stdout().is_terminal() => println!("{}", highlight_matches(line, matches)),
-                        _ => println!("{}", line)
-                    });
-            }
-            process::exit(0)
-        },
-        _ =>
-            process::exit(1)
+    let line_buffered = args.line_buffered || stdout.is_terminal() || is_pipe(&stdout);
+
+    // -o prints no context lines, only the matches of selected lines, and counts print no lines at all.
+    // Quiet mode and file listings need only the first selected line of each file.
+    let listing = args.quiet || args.files_with_matches || args.files_without_match;
+    let no_context = args.only_matching || args.count || args.count_matches || listing;
+    let options = Options {
+        invert: args.invert_match,
+        before_context: if no_context { 0 } else { args.before_context.or(args.context).unwrap_or(0) },
+        after_context: if no_context { 0 } else { args.after_context.or(args.context).unwrap_or(0) },
+        max_count: if listing { Some(1) } else { args.max_count },
+        need_matches: !listing && (args.count_matches || (!args.count && (args.only_matching || colored || args.column))),
+    };
+    let separated = options.before_context > 0 || options.after_context > 0;
+    let show_filename = files.len() > 1 || args.recursive;
+    let out = BufWriter::new(stdout.lock());
+    let mut printer = Printer::new(&args, out, colored, separated, show_filename, line_buffered);
+
+    let searched = search_files(&files, &set, &options, &mut printer);
+    let flushed = printer.flush();
+    // like GNU grep, a line selected with -q outweighs files that could not be read
+    let status = |selected| if printer.failed && !(args.quiet && selected) { 2 } else if selected { 0 } else { 1 };
+    match searched.and_then(|selected| flushed.map(|()| selected)) {
+        Ok(selected) => process::exit(status(selected)),
+        // the reader went away, which is no error if it saw a selected line, but -c and -L also
+        // write output for files without one
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => process::exit(status(printer.selected)),
+        Err(e) => {
+            eprintln!("rgrep: {}", e);
+            process::exit(2);
+        }
     }
 }
 
@@ -477,24 +581,237 @@ fn main() -> io::Result<()> {
 mod tests {
     use super::*;
 
+    fn match_re(input: &str, pattern: &str) -> Vec<String> {
+        super::match_re(input, &RegexSet::new([pattern]).unwrap())
+            .into_iter()
+            .map(|(_, m)| input[m.start..m.end].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn match_spans() {
+        let spans = super::match_re("ab cab", &RegexSet::new(["ab"]).unwrap())
+            .into_iter()
+            .map(|(_, m)| (m.start, m.end))
+            .collect::<Vec<_>>();
+        assert_eq!(spans, vec![(0, 2), (4, 6)]);
+    }
+
+    fn search(input: &str, pattern: &str, options: &Options) -> Vec<Line> {
+        let mut lines = Vec::new();
+        process_lines(input.as_bytes(), &RegexSet::new([pattern]).unwrap(), options, |line| {
+            lines.push(line);
+            Ok(())
+        })
+        .unwrap();
+        lines
+    }
+
+    #[test]
+    fn match_any_pattern() {
+        let set = RegexSet::new(["\\d+", "b\\w", "ab", "x*"]).unwrap();
+        let spans: Vec<_> = super::match_re("ab 12 xbc", &set)
+            .into_iter()
+            .map(|(pattern, m)| (pattern, m.start, m.end))
+            .collect();
+        assert_eq!(spans, vec![(2, 0, 2), (0, 3, 5), (3, 6, 7), (1, 7, 9)]);
+    }
+
+    #[test]
+    fn select_inverted_lines() {
+        let input = "rust1\nscala2\nphp";
+        let lines = search(input, "\\d", &Options { invert: true, ..Options::default() });
+        assert_eq!(lines, vec![Line { number: 3, offset: 13, text: "php".to_string(), matches: vec![], replaced: vec![], context: false }]);
+        let lines = search(input, "s", &Options::default());
+        assert_eq!(lines.iter().map(|line| line.text.as_str()).collect::<Vec<_>>(), vec!["rust1", "scala2"]);
+    }
+
+    #[test]
+    fn track_line_numbers_and_offsets() {
+        let lines = search("a1\r\nbb\nc2", "\\d", &Options::default());
+        assert_eq!(lines.iter().map(|line| (line.number, line.offset)).collect::<Vec<_>>(), vec![(1, 0), (3, 7)]);
+        assert_eq!(lines[0].text, "a1");
+    }
+
     #[test]
-    fn consume_input() {
-        assert_eq!(consume("abc", "\\w", 0, 0, None), (true, 3, 3));
-        assert_eq!(consume("abc ", "\\w", 0, 0, None), (true, 3, 3));
+    fn read_past_invalid_utf8() {
+        let mut lines = Vec::new();
+        let input: &[u8] = b"ok1\n\xff\xfe bad\nok2\n";
+        let options = Options { need_matches: true, ..Options::default() };
+        process_lines(input, &RegexSet::new(["ok|bad"]).unwrap(), &options, |line| {
+            lines.push(line);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(lines.iter().map(|line| (line.number, line.offset, line.text.as_str())).collect::<Vec<_>>(), vec![
+            (1, 0, "ok1"),
+            (2, 4, "\u{FFFD}\u{FFFD} bad"),
+            (3, 11, "ok2"),
+        ]);
+        // offsets count the raw bytes, not the three bytes of each `�`
+        let args = Args::parse_from(["rgrep", "-b", "-o", "-E", "ok|bad"]);
+        let mut printer = Printer::new(&args, Vec::new(), false, false, false, false);
+        for line in &lines {
+            printer.print("f.txt", line).unwrap();
+        }
+        assert_eq!(String::from_utf8(printer.out).unwrap(), "0:ok\n7:bad\n11:ok\n");
+        let args = Args::parse_from(["rgrep", "--column", "-E", "ok|bad"]);
+        assert_eq!(line_prefix(&args, None, &lines[1], None), "4:");
+    }
+
+    #[test]
+    fn build_line_prefix() {
+        let args = Args::parse_from(["rgrep", "-n", "--column", "-b", "-E", "b"]);
+        let line = &search("a\nab b", "b", &Options { need_matches: true, ..Options::default() })[0];
+        assert_eq!(line_prefix(&args, Some("f.txt"), line, None), "f.txt:2:2:2:");
+        assert_eq!(line_prefix(&args, None, line, Some(&line.matches[1].1)), "2:4:5:");
+        let args = Args::parse_from(["rgrep", "-b", "-E", "b"]);
+        assert_eq!(line_prefix(&args, None, line, None), "2:");
+        let line = Line { number: 1, offset: 0, text: "a".to_string(), matches: vec![], replaced: vec![], context: true };
+        assert_eq!(line_prefix(&args, Some("f.txt"), &line, None), "f.txt-0-");
+    }
+
+    #[test]
+    fn pass_context_lines() {
+        let input = "a\nb\nx1\nc\nx2\nd\ne\nf\nx3";
+        let options = Options { before_context: 1, after_context: 1, ..Options::default() };
+        let lines = search(input, "x", &options);
+        let numbers: Vec<_> = lines.iter().map(|line| (line.number, line.context)).collect();
+        assert_eq!(numbers, vec![(2, true), (3, false), (4, true), (5, false), (6, true), (8, true), (9, false)]);
+    }
+
+    #[test]
+    fn stop_after_max_count() {
+        let input = "x1\na\nx2\nb";
+        let lines = search(input, "x", &Options { max_count: Some(1), ..Options::default() });
+        assert_eq!(lines.iter().map(|line| line.number).collect::<Vec<_>>(), vec![1]);
+        let options = Options { max_count: Some(1), after_context: 2, ..Options::default() };
+        let lines = search(input, "x", &options);
+        let numbers: Vec<_> = lines.iter().map(|line| (line.number, line.context)).collect();
+        assert_eq!(numbers, vec![(1, false), (2, true), (3, true)]);
+    }
+
+    #[test]
+    fn report_selection_under_max_count() {
+        let set = RegexSet::new(["x"]).unwrap();
+        let selected = |input: &str, max_count| {
+            process_lines(input.as_bytes(), &set, &Options { max_count, ..Options::default() }, |_| Ok(())).unwrap()
+        };
+        assert!(selected("a\nx1\nx2", Some(1)));
+        assert!(!selected("a\nx1\nx2", Some(0)));
+        assert!(!selected("a\nb", Some(1)));
+    }
+
+    #[test]
+    fn stop_at_first_selected_file_when_quiet() {
+        let dir = std::env::temp_dir().join(format!("rgrep-quiet-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("found.txt"), "a\nx1\n").unwrap();
+        // the second file is never opened once the first one has a selected line
+        let files = ["found.txt", "missing.txt"].map(|name| dir.join(name).to_string_lossy().into_owned());
+        let set = RegexSet::new(["x"]).unwrap();
+        let options = Options { max_count: Some(1), ..Options::default() };
+        let args = Args::parse_from(["rgrep", "-q", "-E", "x"]);
+        let mut printer = Printer::new(&args, Vec::new(), false, false, true, false);
+        let quiet = search_files(&files, &set, &options, &mut printer);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(quiet.unwrap());
+        assert!(!printer.failed);
+    }
+
+    #[test]
+    fn keep_searching_after_unreadable_files() {
+        let dir = std::env::temp_dir().join(format!("rgrep-unreadable-{}", process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("found.txt"), "a\nx1\n").unwrap();
+        let files = ["missing.txt", "sub", "found.txt"].map(|name| dir.join(name).to_string_lossy().into_owned());
+        let set = RegexSet::new(["x"]).unwrap();
+        let options = Options { max_count: Some(1), ..Options::default() };
+        let args = Args::parse_from(["rgrep", "-q", "-E", "x"]);
+        let mut quiet = Printer::new(&args, Vec::new(), false, false, true, false);
+        let selected = search_files(&files, &set, &options, &mut quiet);
+        let args = Args::parse_from(["rgrep", "-l", "-E", "x"]);
+        let mut listing = Printer::new(&args, Vec::new(), false, false, true, false);
+        let listed = search_files(&files, &set, &options, &mut listing);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(selected.unwrap() && quiet.failed);
+        assert!(listed.unwrap() && listing.failed);
+        assert_eq!(String::from_utf8(listing.out).unwrap(), format!("{}\n", files[2]));
+    }
+
+    /// Output whose reader went away
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn track_selection_when_the_reader_goes_away() {
+        let set = RegexSet::new(["x"]).unwrap();
+        let selected = |flags: &[&str], input: &str| {
+            let args = Args::parse_from(["rgrep"].iter().chain(flags).chain(&["-E", "x"]));
+            let options = Options { max_count: args.files_without_match.then_some(1), ..Options::default() };
+            let mut printer = Printer::new(&args, ClosedPipe, false, false, false, false);
+            let searched = search_input(input.as_bytes(), "f.txt", &set, &options, &mut printer);
+            assert_eq!(searched.unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+            printer.selected
+        };
+        assert!(selected(&[], "a\nx"));
+        assert!(selected(&["-c"], "x"));
+        assert!(!selected(&["-c"], "a"));
+        assert!(!selected(&["-L"], "a"));
     }
 
     #[test]
-    fn extract_backreference_pattern() {
-        assert_eq!(extract_pattern("\\123 abc"), "\\123");
-        assert_eq!(extract_pattern("(abc)a"), "(abc)");
-        assert_eq!(extract_pattern("((abc))a"), "((abc))");
+    fn print_separated_groups() {
+        let args = Args::parse_from(["rgrep", "-n", "-C", "1", "-E", "x"]);
+        let options = Options { before_context: 1, after_context: 1, ..Options::default() };
+        let mut printer = Printer::new(&args, Vec::new(), false, true, false, false);
+        for line in search("x1\na\nb\nc\nx2", "x", &options) {
+            printer.print("f.txt", &line).unwrap();
+        }
+        printer.start_file();
+        for line in search("x3", "x", &options) {
+            printer.print("g.txt", &line).unwrap();
+        }
+        assert_eq!(String::from_utf8(printer.out).unwrap(), "1:x1\n2-a\n--\n4-c\n5:x2\n--\n1:x3\n");
     }
 
     #[test]
-    fn find_matching_paren_index() {
-        assert_eq!(find_matching_paren("(abc)"), 4);
-        assert_eq!(find_matching_paren("(abc)a"), 4);
-        assert_eq!(find_matching_paren("((abc))a"), 6);
+    fn count_lines_or_matches() {
+        let line = &search("a1 b2", "\\d", &Options { need_matches: true, ..Options::default() })[0];
+        assert_eq!(line_count(&Args::parse_from(["rgrep", "-c", "-E", "\\d"]), line), 1);
+        assert_eq!(line_count(&Args::parse_from(["rgrep", "--count-matches", "-E", "\\d"]), line), 2);
+        assert!(search("a1 b2", "\\d", &Options::default())[0].matches.is_empty());
+        let line = &search("ab", "\\d", &Options { invert: true, ..Options::default() })[0];
+        assert_eq!(line_count(&Args::parse_from(["rgrep", "--count-matches", "-v", "-E", "\\d"]), line), 1);
+    }
+
+    #[test]
+    fn prefer_text_over_empty_matches() {
+        let line = &search("ba", "x*|a", &Options { need_matches: true, ..Options::default() })[0];
+        assert_eq!(line_count(&Args::parse_from(["rgrep", "--count-matches", "-E", "x*|a"]), line), 1);
+        assert_eq!(highlight_matches(&line.text, &line.matches), "b\x1b[01;31ma\x1b[m");
+        let args = Args::parse_from(["rgrep", "-o", "-E", "x*|a"]);
+        let mut printer = Printer::new(&args, Vec::new(), false, false, false, false);
+        printer.print("f.txt", line).unwrap();
+        assert_eq!(String::from_utf8(printer.out).unwrap(), "a\n");
+    }
+
+    #[test]
+    fn highlight_by_offset() {
+        let line = "a-b b";
+        let matches = super::match_re(line, &RegexSet::new(["b$"]).unwrap());
+        assert_eq!(highlight_matches(line, &matches), "a-b \x1b[01;31mb\x1b[m");
+        let matches = super::match_re("a-b", &RegexSet::new(["x", "b"]).unwrap());
+        assert_eq!(highlight_matches("a-b", &matches), "a-\x1b[01;32mb\x1b[m");
     }
 
     #[test]
@@ -535,6 +852,15 @@ mod tests {
         assert_eq!(match_re("rust123", "[ust][\\d]\\d"), vec!["t12"]);
     }
 
+    #[test]
+    fn match_ranges() {
+        assert_eq!(match_re("rust 2024", "[a-z]+"), vec!["rust"]);
+        assert_eq!(match_re("0x1F", "[0-9A-F]+"), vec!["0", "1F"]);
+        assert_eq!(match_re("a-b_c", "[^a-zA-Z_]"), vec!["-"]);
+        assert_eq!(match_re("a-]", "[]-]+"), vec!["-]"]);
+        assert_eq!(match_re("λόγος", "[α-ω]+"), vec!["λ", "γος"]);
+    }
+
     #[test]
     fn match_groups_neg() {
         assert_eq!(match_re("r", "[^a]"), vec!["r"]);
@@ -577,7 +903,7 @@ mod tests {
         assert_eq!(match_re("", ".?"), vec![] as Vec<String>);
         assert_eq!(match_re("cat", "c.t"), vec!["cat"]);
         assert_eq!(match_re("rust", "ru.?[abt]"), vec!["rust"]);
-        assert_eq!(match_re("rust", "rus.?t"), vec![] as Vec<String>);
+        assert_eq!(match_re("rust", "rus.?t"), vec!["rust"]);
         assert_eq!(match_re("abc", "..."), vec!["abc"]);
     }
 
@@ -0,0 +1,774 @@
+use crate::class::{case_variants, is_word_char, ClassSet};
+use crate::error::{ErrorKind, PatternError};
+
+const MAX_REPETITION: u32 = 1000;
+/// Largest number of NFA instructions a pattern may compile to, counted repeats are unrolled
+const MAX_PROGRAM_SIZE: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Look {
+    Start,
+    End,
+    /// Not preceded by a word char, what a whole word match starts with
+    WordStartHalf { unicode: bool },
+    /// Not followed by a word char
+    WordEndHalf { unicode: bool },
+    /// `\b`, between a word char and a non-word char or the edge of the input
+    WordBoundary { unicode: bool },
+    /// `\B`, anywhere `\b` does not hold
+    NotWordBoundary { unicode: bool },
+    /// `\<`, before the first char of a word
+    WordStart { unicode: bool },
+    /// `\>`, after the last char of a word
+    WordEnd { unicode: bool },
+}
+
+impl Look {
+    /// Whether the assertion holds at byte offset `pos` of `input`
+    pub fn holds(self, input: &str, pos: usize) -> bool {
+        match self {
+            Look::Start => pos == 0,
+            Look::End => pos == input.len(),
+            _ => self.holds_around(
+                |unicode| input[..pos].chars().next_back().is_some_and(|c| is_word_char(c, unicode)),
+                |unicode| input[pos..].chars().next().is_some_and(|c| is_word_char(c, unicode)),
+            ),
+        }
+    }
+
+    /// Whether a word assertion holds given whether the chars before and after the position are
+    /// word chars, asked with the `unicode` flag of the assertion
+    pub fn holds_around(self, word_before: impl Fn(bool) -> bool, word_after: impl Fn(bool) -> bool) -> bool {
+        match self {
+            Look::Start | Look::End => unreachable!("anchors do not depend on the chars around"),
+            Look::WordStartHalf { unicode } => !word_before(unicode),
+            Look::WordEndHalf { unicode } => !word_after(unicode),
+            Look::WordBoundary { unicode } => word_before(unicode) != word_after(unicode),
+            Look::NotWordBoundary { unicode } => word_before(unicode) == word_after(unicode),
+            Look::WordStart { unicode } => !word_before(unicode) && word_after(unicode),
+            Look::WordEnd { unicode } => word_before(unicode) && !word_after(unicode),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Empty,
+    Literal(char),
+    Class(ClassSet),
+    Look(Look),
+    Group { index: usize, node: Box<Node> },
+    Concat(Vec<Node>),
+    Alternation(Vec<Node>),
+    Repeat { node: Box<Node>, min: u32, max: Option<u32> },
+    Backreference(usize),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Flags {
+    pub case_insensitive: bool,
+    /// Unicode rather than ASCII `\d`, `\s`, `\w` and word chars
+    pub unicode: bool,
+}
+
+/// Pattern compiled into a tree, capture groups are numbered from 1 by their opening paren
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ast {
+    pub root: Node,
+    pub groups: usize,
+    pub flags: Flags,
+    /// Whether any literal char of the pattern, escapes aside, is uppercase
+    pub uppercase: bool,
+}
+
+impl Node {
+    /// Upper bound of the number of instructions `compile` emits for the node
+    pub fn program_size(&self) -> usize {
+        match self {
+            Node::Empty => 0,
+            Node::Literal(_) | Node::Class(_) | Node::Look(_) | Node::Backreference(_) => 1,
+            Node::Group { node, .. } => node.program_size().saturating_add(2),
+            Node::Concat(nodes) => nodes.iter().fold(0, |size, node| size.saturating_add(node.program_size())),
+            Node::Alternation(branches) => branches
+                .iter()
+                .fold(2 * branches.len(), |size, branch| size.saturating_add(branch.program_size())),
+            Node::Repeat { node, min, max } => {
+                let size = node.program_size();
+                let optional = match max {
+                    None => size.saturating_add(2),
+                    Some(max) => size.saturating_add(1).saturating_mul((max - min) as usize),
+                };
+                size.saturating_mul(*min as usize).saturating_add(optional)
+            }
+        }
+    }
+}
+
+impl Ast {
+    /// Makes every match satisfy `before` at its start and `after` at its end
+    pub fn surround(&mut self, before: Look, after: Look) {
+        let root = std::mem::replace(&mut self.root, Node::Empty);
+        self.root = Node::Concat(vec![Node::Look(before), root, Node::Look(after)]);
+    }
+}
+
+#[cfg(test)]
+pub fn parse(pattern: &str) -> Result<Ast, PatternError> {
+    parse_with(pattern, Flags::default())
+}
+
+pub fn parse_with(pattern: &str, flags: Flags) -> Result<Ast, PatternError> {
+    let mut parser = Parser { pattern, pos: 0, groups: 0, backreferences: Vec::new(), flags, uppercase: false };
+    let root = parser.parse_alternation()?;
+    if parser.peek().is_some() {
+        return Err(parser.error(ErrorKind::UnopenedGroup, parser.pos, "unopened group"));
+    }
+    if root.program_size() > MAX_PROGRAM_SIZE {
+        return Err(parser.program_too_large(0));
+    }
+    if let Some(&(_, offset)) = parser.backreferences.iter().find(|(index, _)| *index > parser.groups) {
+        return Err(parser.error(ErrorKind::InvalidBackreference, offset, "backreference to undefined group"));
+    }
+    Ok(Ast { root, groups: parser.groups, flags, uppercase: parser.uppercase })
+}
+
+/// Takes every char of the pattern literally, as fixed strings do
+pub fn parse_literal(pattern: &str, flags: Flags) -> Ast {
+    let mut parser = Parser { pattern, pos: 0, groups: 0, backreferences: Vec::new(), flags, uppercase: false };
+    let root = Node::Concat(pattern.chars().map(|c| parser.literal(c)).collect());
+    Ast { root, groups: 0, flags, uppercase: parser.uppercase }
+}
+
+struct Parser<'a> {
+    pattern: &'a str,
+    pos: usize,
+    groups: usize,
+    backreferences: Vec<(usize, usize)>,
+    flags: Flags,
+    uppercase: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, kind: ErrorKind, offset: usize, message: &str) -> PatternError {
+        PatternError::new(kind, offset, message, self.pattern)
+    }
+
+    fn program_too_large(&self, offset: usize) -> PatternError {
+        let message = format!("pattern compiles to more than {} instructions", MAX_PROGRAM_SIZE);
+        self.error(ErrorKind::ProgramTooLarge, offset, &message)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.pattern[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.pos += expected.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_alternation(&mut self) -> Result<Node, PatternError> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.eat('|') {
+            branches.push(self.parse_concat()?);
+        }
+        if branches.len() == 1 {
+            Ok(branches.pop().unwrap_or(Node::Empty))
+        } else {
+            Ok(Node::Alternation(branches))
+        }
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, PatternError> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            nodes.push(self.parse_repeat()?);
+        }
+        match nodes.len() {
+            0 => Ok(Node::Empty),
+            1 => Ok(nodes.pop().unwrap_or(Node::Empty)),
+            _ => Ok(Node::Concat(nodes)),
+        }
+    }
+
+    fn parse_repeat(&mut self) -> Result<Node, PatternError> {
+        let mut node = self.parse_atom()?;
+        loop {
+            let (min, max) = match self.peek() {
+                Some('+') => (1, None),
+                Some('*') => (0, None),
+                Some('?') => (0, Some(1)),
+                Some('{') => {
+                    let start = self.pos;
+                    let bounds = self.parse_bounds()?;
+                    node = Node::Repeat { node: Box::new(node), min: bounds.0, max: bounds.1 };
+                    if node.program_size() > MAX_PROGRAM_SIZE {
+                        return Err(self.program_too_large(start));
+                    }
+                    continue;
+                }
+                _ => return Ok(node),
+            };
+            self.pos += 1;
+            node = Node::Repeat { node: Box::new(node), min, max };
+        }
+    }
+
+    fn parse_bounds(&mut self) -> Result<(u32, Option<u32>), PatternError> {
+        let start = self.pos;
+        self.pos += 1;
+        let min = self.parse_number()?;
+        let comma = self.eat(',');
+        let max = if comma { self.parse_number()? } else { min };
+        if !self.eat('}') {
+            return Err(self.error(ErrorKind::UnclosedRepetition, start, "unclosed repetition"));
+        }
+        if min.is_none() && (!comma || max.is_none()) {
+            return Err(self.error(ErrorKind::InvalidRepetition, start, "repetition has no bounds"));
+        }
+        let min = min.unwrap_or(0);
+        if max.is_some_and(|max| max < min) {
+            return Err(self.error(ErrorKind::InvalidRepetition, start, "repetition minimum is greater than maximum"));
+        }
+        if min.max(max.unwrap_or(0)) > MAX_REPETITION {
+            let message = format!("repetition count exceeds the limit of {}", MAX_REPETITION);
+            return Err(self.error(ErrorKind::RepetitionTooLarge, start, &message));
+        }
+        Ok((min, max))
+    }
+
+    fn parse_number(&mut self) -> Result<Option<u32>, PatternError> {
+        let digits = self.pattern[self.pos..].chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 {
+            return Ok(None);
+        }
+        let start = self.pos;
+        self.pos += digits;
+        match self.pattern[start..self.pos].parse::<u32>() {
+            Ok(number) => Ok(Some(number)),
+            Err(_) => Err(self.error(ErrorKind::RepetitionTooLarge, start, "number is too large")),
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, PatternError> {
+        let start = self.pos;
+        match self.next() {
+            Some('.') => Ok(Node::Class(ClassSet::any())),
+            Some('^') => Ok(Node::Look(Look::Start)),
+            Some('$') => Ok(Node::Look(Look::End)),
+            Some('[') => self.parse_class(),
+            Some('\\') => self.parse_escape(),
+            Some('(') => {
+                self.groups += 1;
+                let index = self.groups;
+                let node = self.parse_alternation()?;
+                if !self.eat(')') {
+                    return Err(self.error(ErrorKind::UnclosedGroup, start, "unclosed group"));
+                }
+                Ok(Node::Group { index, node: Box::new(node) })
+            }
+            Some('+' | '*' | '?' | '{') => {
+                Err(self.error(ErrorKind::RepetitionMissing, start, "repetition operator missing expression"))
+            }
+            Some(c) => Ok(self.literal(c)),
+            None => Ok(Node::Empty),
+        }
+    }
+
+    fn literal(&mut self, c: char) -> Node {
+        self.uppercase |= c.is_uppercase();
+        if self.flags.case_insensitive && !case_variants(c).is_empty() {
+            let mut set = ClassSet::from_ranges(&[(c, c)]);
+            set.case_fold();
+            Node::Class(set)
+        } else {
+            Node::Literal(c)
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, PatternError> {
+        let start = self.pos - 1;
+        match self.peek() {
+            Some(c @ ('d' | 'D' | 's' | 'S' | 'w' | 'W')) => {
+                self.pos += 1;
+                Ok(Node::Class(self.perl_class(c)))
+            }
+            Some(c @ ('p' | 'P')) => {
+                self.pos += 1;
+                Ok(Node::Class(self.parse_property(start, c == 'P')?))
+            }
+            Some(c @ ('b' | 'B' | '<' | '>')) => {
+                self.pos += 1;
+                let unicode = self.flags.unicode;
+                Ok(Node::Look(match c {
+                    'b' => Look::WordBoundary { unicode },
+                    'B' => Look::NotWordBoundary { unicode },
+                    '<' => Look::WordStart { unicode },
+                    _ => Look::WordEnd { unicode },
+                }))
+            }
+            Some('1'..='9') => match self.parse_number() {
+                Ok(Some(index)) => {
+                    self.backreferences.push((index as usize, start));
+                    Ok(Node::Backreference(index as usize))
+                }
+                _ => Err(self.error(ErrorKind::InvalidBackreference, start, "invalid backreference")),
+            },
+            Some(c) => match self.parse_char_escape(start)? {
+                Some(escaped) => Ok(self.literal(escaped)),
+                None => Err(self.unsupported_escape(start, c)),
+            },
+            None => Err(self.error(ErrorKind::TrailingEscape, start, "trailing backslash")),
+        }
+    }
+
+    /// Escapes that stand for a single char, `None` when the escape after `\` is not one of them
+    fn parse_char_escape(&mut self, start: usize) -> Result<Option<char>, PatternError> {
+        let escaped = match self.peek() {
+            Some(c) if c.is_ascii_punctuation() => c,
+            Some('t') => '\t',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('x') => {
+                self.pos += 1;
+                return self.parse_code_point(start, 16, 2, 2).map(Some);
+            }
+            Some('u') => {
+                self.pos += 1;
+                return self.parse_braced_code_point(start, 16, 6).map(Some);
+            }
+            Some('o') => {
+                self.pos += 1;
+                return self.parse_braced_code_point(start, 8, 8).map(Some);
+            }
+            // the leading 0 tells octal escapes apart from backreferences
+            Some('0') => return self.parse_code_point(start, 8, 1, 3).map(Some),
+            _ => return Ok(None),
+        };
+        self.pos += 1;
+        Ok(Some(escaped))
+    }
+
+    fn parse_braced_code_point(&mut self, start: usize, radix: u32, max_digits: usize) -> Result<char, PatternError> {
+        if !self.eat('{') {
+            return Err(self.error(ErrorKind::InvalidEscape, start, "expected { after the escape"));
+        }
+        let c = self.parse_code_point(start, radix, 1, max_digits)?;
+        if !self.eat('}') {
+            return Err(self.error(ErrorKind::InvalidEscape, start, "expected } after the code point"));
+        }
+        Ok(c)
+    }
+
+    /// Reads between `min_digits` and `max_digits` digits in `radix` as a code point
+    fn parse_code_point(&mut self, start: usize, radix: u32, min_digits: usize, max_digits: usize) -> Result<char, PatternError> {
+        let digits = self.pattern[self.pos..].chars().take(max_digits).take_while(|c| c.is_digit(radix)).count();
+        if digits < min_digits {
+            let message = match radix {
+                16 => format!("expected {} hex digits in the escape", min_digits),
+                _ => format!("expected {} octal digits in the escape", min_digits),
+            };
+            return Err(self.error(ErrorKind::InvalidEscape, start, &message));
+        }
+        let value = u32::from_str_radix(&self.pattern[self.pos..self.pos + digits], radix).ok();
+        self.pos += digits;
+        value
+            .and_then(char::from_u32)
+            .ok_or_else(|| self.error(ErrorKind::InvalidEscape, start, "escape is not a valid code point"))
+    }
+
+    fn unsupported_escape(&self, start: usize, c: char) -> PatternError {
+        self.error(ErrorKind::UnsupportedEscape, start, &format!("unsupported escape sequence \\{}", c))
+    }
+
+    fn parse_class(&mut self) -> Result<Node, PatternError> {
+        let start = self.pos - 1;
+        let negated = self.eat('^');
+        // GNU grep rejects the common mistake of a POSIX class outside a bracket expression
+        if let Some((name, _)) = self.posix_class_name(start)
+            && ClassSet::posix(name).is_some()
+        {
+            let message = format!("character class syntax is [[:{}:]], not [:{}:]", name, name);
+            return Err(self.error(ErrorKind::UnknownClass, start, &message));
+        }
+        let mut set = ClassSet::new();
+        let mut shorthands = ClassSet::new();
+        let mut first = true;
+        loop {
+            let offset = self.pos;
+            if let Some((name, len)) = self.posix_class_name(self.pos) {
+                let negated = name.starts_with('^');
+                let Some(mut members) = ClassSet::posix(name.trim_start_matches('^')) else {
+                    let message = format!("unknown POSIX class {}", name);
+                    return Err(self.error(ErrorKind::UnknownClass, offset, &message));
+                };
+                if self.flags.case_insensitive {
+                    members.case_fold();
+                }
+                if negated {
+                    members.negate();
+                }
+                shorthands.union(&members);
+                self.pos += len;
+                first = false;
+                continue;
+            }
+            let item = match self.next() {
+                // a leading ] is a member rather than the end of an empty class
+                Some(']') if !first => break,
+                Some('\\') => self.parse_class_escape(start)?,
+                Some(c) => ClassItem::Char(c),
+                None => return Err(self.error(ErrorKind::UnclosedClass, start, "unclosed character class")),
+            };
+            first = false;
+            match item {
+                ClassItem::Set(members) => shorthands.union(&members),
+                ClassItem::Char(from) if self.is_range_dash() => {
+                    self.pos += 1;
+                    let end = self.pos;
+                    let to = match self.next() {
+                        Some('\\') => self.parse_class_escape(start)?,
+                        Some(c) => ClassItem::Char(c),
+                        None => return Err(self.error(ErrorKind::UnclosedClass, start, "unclosed character class")),
+                    };
+                    match to {
+                        ClassItem::Char(to) if from <= to => {
+                            self.uppercase |= from.is_uppercase() || to.is_uppercase();
+                            set.push(from, to);
+                        }
+                        ClassItem::Char(_) => {
+                            return Err(self.error(ErrorKind::InvalidClassRange, offset, "class range start is greater than its end"));
+                        }
+                        ClassItem::Set(_) => {
+                            return Err(self.error(ErrorKind::InvalidClassRange, end, "class range end must be a single character"));
+                        }
+                    }
+                }
+                ClassItem::Char(c) => {
+                    self.uppercase |= c.is_uppercase();
+                    set.push(c, c);
+                }
+            }
+        }
+        if self.flags.case_insensitive {
+            set.case_fold();
+        }
+        set.union(&shorthands);
+        if negated {
+            set.negate();
+        }
+        Ok(Node::Class(set))
+    }
+
+    /// Set of a shorthand class like `\d`, the uppercase letter negates it
+    fn perl_class(&self, c: char) -> ClassSet {
+        let unicode = self.flags.unicode;
+        let mut set = match c.to_ascii_lowercase() {
+            'd' => ClassSet::digit(unicode),
+            's' => ClassSet::space(unicode),
+            _ => ClassSet::word(unicode),
+        };
+        if c.is_ascii_uppercase() {
+            set.negate();
+        }
+        set
+    }
+
+    /// Reads the name of `\p{name}` or the single letter of `\pL`
+    fn parse_property(&mut self, start: usize, negated: bool) -> Result<ClassSet, PatternError> {
+        let pattern = self.pattern;
+        let name = if self.eat('{') {
+            let Some(len) = pattern[self.pos..].find('}') else {
+                return Err(self.error(ErrorKind::InvalidEscape, start, "expected } after the property name"));
+            };
+            self.pos += len + 1;
+            &pattern[self.pos - len - 1..self.pos - 1]
+        } else {
+            let from = self.pos;
+            if self.next().is_none() {
+                return Err(self.error(ErrorKind::TrailingEscape, start, "expected a property name"));
+            }
+            &pattern[from..self.pos]
+        };
+        let Some(mut set) = ClassSet::unicode_property(name) else {
+            return Err(self.error(ErrorKind::UnknownProperty, start, &format!("unknown Unicode property {}", name)));
+        };
+        if self.flags.case_insensitive {
+            set.case_fold();
+        }
+        if negated {
+            set.negate();
+        }
+        Ok(set)
+    }
+
+    /// Name of a `[:name:]` at `pos` and the length of the whole bracket
+    fn posix_class_name(&self, pos: usize) -> Option<(&'a str, usize)> {
+        let pattern = self.pattern;
+        let rest = pattern[pos..].strip_prefix("[:")?;
+        let len = rest.find(":]")?;
+        Some((&rest[..len], len + 4))
+    }
+
+    /// A dash forms a range unless it is the last member of the class
+    fn is_range_dash(&self) -> bool {
+        let mut rest = self.pattern[self.pos..].chars();
+        rest.next() == Some('-') && !matches!(rest.next(), Some(']') | None)
+    }
+
+    fn parse_class_escape(&mut self, start: usize) -> Result<ClassItem, PatternError> {
+        let offset = self.pos - 1;
+        match self.peek() {
+            Some(c @ ('d' | 'D' | 's' | 'S' | 'w' | 'W')) => {
+                self.pos += 1;
+                Ok(ClassItem::Set(self.perl_class(c)))
+            }
+            Some(c @ ('p' | 'P')) => {
+                self.pos += 1;
+                Ok(ClassItem::Set(self.parse_property(offset, c == 'P')?))
+            }
+            Some(c) => match self.parse_char_escape(offset)? {
+                Some(escaped) => Ok(ClassItem::Char(escaped)),
+                None => Err(self.unsupported_escape(offset, c)),
+            },
+            None => Err(self.error(ErrorKind::UnclosedClass, start, "unclosed character class")),
+        }
+    }
+}
+
+enum ClassItem {
+    Char(char),
+    Set(ClassSet),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root(pattern: &str) -> Node {
+        parse(pattern).unwrap().root
+    }
+
+    #[test]
+    fn parse_literals() {
+        assert_eq!(root("ab"), Node::Concat(vec![Node::Literal('a'), Node::Literal('b')]));
+        assert_eq!(root("œ"), Node::Literal('œ'));
+        assert_eq!(root(""), Node::Empty);
+    }
+
+    #[test]
+    fn parse_escapes() {
+        let literals = |chars: &str| Node::Concat(chars.chars().map(Node::Literal).collect());
+        assert_eq!(root(r"\.\\\(\)\[\{\|\^\$\*\+\?"), literals(r".\()[{|^$*+?"));
+        assert_eq!(root(r"\t\n\r"), literals("\t\n\r"));
+        assert_eq!(root(r"\x41\u{e9}\u{1F600}"), literals("Aé\u{1F600}"));
+        assert_eq!(root(r"\0\012\o{101}"), literals("\0\nA"));
+        let class = ClassSet::from_ranges(&[('\t', '\t'), ('0', '9'), (']', ']')]);
+        assert_eq!(root(r"[\x30-\x39\]\t]"), Node::Class(class));
+        assert!(parse(r"\x41").unwrap().uppercase);
+    }
+
+    #[test]
+    fn parse_posix_classes() {
+        let class = |ranges: &[(char, char)]| Node::Class(ClassSet::from_ranges(ranges));
+        assert_eq!(root("[[:xdigit:]]"), class(&[('0', '9'), ('A', 'F'), ('a', 'f')]));
+        assert_eq!(root("[_[:alpha:][:digit:]-]"), class(&[('-', '-'), ('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')]));
+        assert_eq!(root("[^[:^space:]]"), class(&[('\t', '\r'), (' ', ' ')]));
+        assert_eq!(root("[[:]"), class(&[(':', ':'), ('[', '[')]));
+        let flags = Flags { case_insensitive: true, ..Flags::default() };
+        let Node::Class(set) = parse_with("[[:upper:]]", flags).unwrap().root else { panic!() };
+        assert!(set.contains('q') && set.contains('\u{17F}'));
+    }
+
+    #[test]
+    fn parse_unicode_properties() {
+        let property = |query| ClassSet::unicode_property(query).unwrap();
+        assert_eq!(root(r"\pL"), Node::Class(property("L")));
+        assert_eq!(root(r"\p{Greek}"), Node::Class(property("Greek")));
+        let mut not_ascii = property("ASCII");
+        not_ascii.negate();
+        assert_eq!(root(r"\P{ASCII}"), Node::Class(not_ascii.clone()));
+        let mut digits_or_not_ascii = property("Nd");
+        digits_or_not_ascii.union(&not_ascii);
+        assert_eq!(root(r"[\p{Nd}\P{ASCII}]"), Node::Class(digits_or_not_ascii));
+        assert!(!parse(r"\p{Lu}").unwrap().uppercase);
+    }
+
+    #[test]
+    fn parse_quantifiers() {
+        let repeat = |min, max| Node::Repeat { node: Box::new(Node::Literal('a')), min, max };
+        assert_eq!(root("a+"), repeat(1, None));
+        assert_eq!(root("a*"), repeat(0, None));
+        assert_eq!(root("a?"), repeat(0, Some(1)));
+        assert_eq!(root("a{3}"), repeat(3, Some(3)));
+        assert_eq!(root("a{3,}"), repeat(3, None));
+        assert_eq!(root("a{2,5}"), repeat(2, Some(5)));
+        assert_eq!(root("a{,5}"), repeat(0, Some(5)));
+    }
+
+    #[test]
+    fn parse_groups() {
+        let ast = parse("((c|d)) \\2").unwrap();
+        assert_eq!(ast.groups, 2);
+        assert_eq!(ast.root, Node::Concat(vec![
+            Node::Group {
+                index: 1,
+                node: Box::new(Node::Group {
+                    index: 2,
+                    node: Box::new(Node::Alternation(vec![Node::Literal('c'), Node::Literal('d')])),
+                }),
+            },
+            Node::Literal(' '),
+            Node::Backreference(2),
+        ]));
+    }
+
+    #[test]
+    fn parse_classes() {
+        assert_eq!(root("[a\\db]"), Node::Class(ClassSet::from_ranges(&[('0', '9'), ('a', 'b')])));
+        assert_eq!(root("\\w"), Node::Class(ClassSet::word(false)));
+        let mut negated = ClassSet::from_ranges(&[('a', 'a')]);
+        negated.negate();
+        assert_eq!(root("[^a]"), Node::Class(negated));
+    }
+
+    #[test]
+    fn parse_class_ranges() {
+        let class = |ranges: &[(char, char)]| Node::Class(ClassSet::from_ranges(ranges));
+        assert_eq!(root("[a-z]"), class(&[('a', 'z')]));
+        assert_eq!(root("[0-9A-F]"), class(&[('0', '9'), ('A', 'F')]));
+        assert_eq!(root("[α-ω]"), class(&[('α', 'ω')]));
+        assert_eq!(root("[-a]"), class(&[('-', '-'), ('a', 'a')]));
+        assert_eq!(root("[a-]"), class(&[('-', '-'), ('a', 'a')]));
+        assert_eq!(root("[]a]"), class(&[(']', ']'), ('a', 'a')]));
+        assert_eq!(root("[\\]\\\\]"), class(&[('\\', ']')]));
+        assert_eq!(root("[!--]"), class(&[('!', '-')]));
+        assert_eq!(root("[a\\-z]"), class(&[('-', '-'), ('a', 'a'), ('z', 'z')]));
+        let mut negated = ClassSet::from_ranges(&[('A', 'Z'), ('_', '_'), ('a', 'z')]);
+        negated.negate();
+        assert_eq!(root("[^a-zA-Z_]"), Node::Class(negated));
+        let mut negated = ClassSet::from_ranges(&[(']', ']')]);
+        negated.negate();
+        assert_eq!(root("[^]]"), Node::Class(negated));
+    }
+
+    #[test]
+    fn parse_case_insensitive_ranges() {
+        let flags = Flags { case_insensitive: true, ..Flags::default() };
+        assert_eq!(
+            parse_with("[a-c]", flags).unwrap().root,
+            Node::Class(ClassSet::from_ranges(&[('A', 'C'), ('a', 'c')]))
+        );
+    }
+
+    #[test]
+    fn parse_case_insensitive() {
+        let flags = Flags { case_insensitive: true, ..Flags::default() };
+        let folded = |ranges: &[(char, char)]| Node::Class(ClassSet::from_ranges(ranges));
+        assert_eq!(parse_with("a1", flags).unwrap().root, Node::Concat(vec![
+            folded(&[('A', 'A'), ('a', 'a')]),
+            Node::Literal('1'),
+        ]));
+        let mut negated = ClassSet::from_ranges(&[('0', '9'), ('B', 'B'), ('b', 'b')]);
+        negated.negate();
+        assert_eq!(parse_with("[^b\\d]", flags).unwrap().root, Node::Class(negated));
+        assert_eq!(parse_with("\\w", flags).unwrap().root, Node::Class(ClassSet::word(false)));
+    }
+
+    #[test]
+    fn parse_fixed_strings() {
+        let ast = parse_literal("a.(", Flags::default());
+        assert_eq!(ast.root, Node::Concat(vec![Node::Literal('a'), Node::Literal('.'), Node::Literal('(')]));
+        let ast = parse_literal("A1", Flags { case_insensitive: true, ..Flags::default() });
+        assert_eq!(ast.root, Node::Concat(vec![
+            Node::Class(ClassSet::from_ranges(&[('A', 'A'), ('a', 'a')])),
+            Node::Literal('1'),
+        ]));
+        assert!(ast.uppercase);
+    }
+
+    #[test]
+    fn detect_uppercase_literals() {
+        assert!(parse("aB").unwrap().uppercase);
+        assert!(parse("[xÉ]").unwrap().uppercase);
+        assert!(!parse("a\\d\\w").unwrap().uppercase);
+    }
+
+    #[test]
+    fn parse_anchors() {
+        assert_eq!(root("^a$"), Node::Concat(vec![
+            Node::Look(Look::Start),
+            Node::Literal('a'),
+            Node::Look(Look::End),
+        ]));
+        assert_eq!(root("\\<a\\b"), Node::Concat(vec![
+            Node::Look(Look::WordStart { unicode: false }),
+            Node::Literal('a'),
+            Node::Look(Look::WordBoundary { unicode: false }),
+        ]));
+    }
+
+    #[test]
+    fn check_word_boundaries() {
+        let holds = |look: Look| (0..=5).filter(|&pos| look.holds("ab, c", pos)).collect::<Vec<_>>();
+        assert_eq!(holds(Look::WordBoundary { unicode: false }), vec![0, 2, 4, 5]);
+        assert_eq!(holds(Look::NotWordBoundary { unicode: false }), vec![1, 3]);
+        assert_eq!(holds(Look::WordStart { unicode: false }), vec![0, 4]);
+        assert_eq!(holds(Look::WordEnd { unicode: false }), vec![2, 5]);
+        assert!(!Look::WordBoundary { unicode: false }.holds("é", 0));
+        assert!(Look::WordBoundary { unicode: true }.holds("é", 0));
+    }
+
+    fn error(pattern: &str) -> (ErrorKind, usize) {
+        let error = parse(pattern).unwrap_err();
+        (error.kind, error.offset)
+    }
+
+    #[test]
+    fn reject_invalid_patterns() {
+        assert_eq!(error("a(b(c)"), (ErrorKind::UnclosedGroup, 1));
+        assert_eq!(error("ab)c"), (ErrorKind::UnopenedGroup, 2));
+        assert_eq!(error("x[ab"), (ErrorKind::UnclosedClass, 1));
+        assert_eq!(error("[a\\"), (ErrorKind::UnclosedClass, 0));
+        assert_eq!(error("a{2"), (ErrorKind::UnclosedRepetition, 1));
+        assert_eq!(error("a{3,1}"), (ErrorKind::InvalidRepetition, 1));
+        assert_eq!(error("a{}"), (ErrorKind::InvalidRepetition, 1));
+        assert_eq!(error("a{,}"), (ErrorKind::InvalidRepetition, 1));
+        assert_eq!(error("a{1001}"), (ErrorKind::RepetitionTooLarge, 1));
+        assert_eq!(error("x(a{1000}){1000}"), (ErrorKind::ProgramTooLarge, 10));
+        assert_eq!(error("((a{1000}){1000}){1000}"), (ErrorKind::ProgramTooLarge, 10));
+        assert_eq!(error(&"(a{1000}){50}".repeat(3)), (ErrorKind::ProgramTooLarge, 0));
+        assert_eq!(error("a|+a"), (ErrorKind::RepetitionMissing, 2));
+        assert_eq!(error("ab\\"), (ErrorKind::TrailingEscape, 2));
+        assert_eq!(error("a\\q"), (ErrorKind::UnsupportedEscape, 1));
+        assert_eq!(error("[a\\q]"), (ErrorKind::UnsupportedEscape, 2));
+        assert_eq!(error("a\\x4"), (ErrorKind::InvalidEscape, 1));
+        assert_eq!(error("\\u41"), (ErrorKind::InvalidEscape, 0));
+        assert_eq!(error("\\u{41"), (ErrorKind::InvalidEscape, 0));
+        assert_eq!(error("\\u{D800}"), (ErrorKind::InvalidEscape, 0));
+        assert_eq!(error("\\o{}"), (ErrorKind::InvalidEscape, 0));
+        assert_eq!(error("a\\p{Klingon}"), (ErrorKind::UnknownProperty, 1));
+        assert_eq!(error("[\\p{L]"), (ErrorKind::InvalidEscape, 1));
+        assert_eq!(error("\\p"), (ErrorKind::TrailingEscape, 0));
+        assert_eq!(error("a[[:word:]]"), (ErrorKind::UnknownClass, 2));
+        assert_eq!(error("[:space:]"), (ErrorKind::UnknownClass, 0));
+        assert_eq!(error("(a)\\2"), (ErrorKind::InvalidBackreference, 3));
+        assert_eq!(error("[]"), (ErrorKind::UnclosedClass, 0));
+        assert_eq!(error("[a-"), (ErrorKind::UnclosedClass, 0));
+        assert_eq!(error("x[z-a]"), (ErrorKind::InvalidClassRange, 2));
+        assert_eq!(error("[a-\\d]"), (ErrorKind::InvalidClassRange, 3));
+    }
+}
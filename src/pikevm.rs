@@ -0,0 +1,252 @@
+use crate::backtrack::{spans, Spans};
+use crate::compile::{Inst, Program};
+
+/// Finds the leftmost match starting at or after `start` by simulating every NFA thread in lockstep,
+/// so the running time is bounded by the input length times the program size
+pub fn find_at(program: &Program, input: &str, start: usize) -> Option<Spans> {
+    PikeVm::new(program, input).run(start, false).map(|slots| spans(&slots))
+}
+
+/// Like [`find_at`] but passes over empty matches, so an alternative matching some text at the
+/// same position wins instead
+pub fn find_non_empty_at(program: &Program, input: &str, start: usize) -> Option<Spans> {
+    PikeVm::new(program, input).run(start, true).map(|slots| spans(&slots))
+}
+
+/// Marks every pattern of a set program that matches somewhere in `input`
+pub fn which_match(program: &Program, input: &str, matched: &mut [bool]) {
+    PikeVm::new(program, input).run_all(matched)
+}
+
+type Slots = Vec<Option<usize>>;
+
+/// Ordered set of program counters with capture slots stored per counter
+struct Threads {
+    dense: Vec<usize>,
+    sparse: Vec<usize>,
+    slots: Vec<Option<usize>>,
+    width: usize,
+}
+
+impl Threads {
+    fn new(program: &Program) -> Self {
+        Threads {
+            dense: Vec::with_capacity(program.insts.len()),
+            sparse: vec![0; program.insts.len()],
+            slots: vec![None; program.insts.len() * program.slots],
+            width: program.slots,
+        }
+    }
+
+    fn contains(&self, pc: usize) -> bool {
+        let i = self.sparse[pc];
+        i < self.dense.len() && self.dense[i] == pc
+    }
+
+    fn insert(&mut self, pc: usize) {
+        self.sparse[pc] = self.dense.len();
+        self.dense.push(pc);
+    }
+
+    fn slots(&self, pc: usize) -> &[Option<usize>] {
+        &self.slots[pc * self.width..(pc + 1) * self.width]
+    }
+
+    fn clear(&mut self) {
+        self.dense.clear();
+    }
+}
+
+enum Frame {
+    Explore(usize),
+    Restore(usize, Option<usize>),
+}
+
+struct PikeVm<'a> {
+    program: &'a Program,
+    input: &'a str,
+    current: Threads,
+    next: Threads,
+    stack: Vec<Frame>,
+    scratch: Slots,
+}
+
+impl<'a> PikeVm<'a> {
+    fn new(program: &'a Program, input: &'a str) -> Self {
+        PikeVm {
+            program,
+            input,
+            current: Threads::new(program),
+            next: Threads::new(program),
+            stack: Vec::new(),
+            scratch: vec![None; program.slots],
+        }
+    }
+
+    fn run(&mut self, start: usize, non_empty: bool) -> Option<Slots> {
+        let mut matched = None;
+        let mut pos = start;
+        loop {
+            if matched.is_none() {
+                self.scratch.iter_mut().for_each(|slot| *slot = None);
+                self.add_thread(true, 0, pos);
+            }
+            if self.current.dense.is_empty() {
+                break;
+            }
+            let c = self.input[pos..].chars().next();
+            let next_pos = pos + c.map_or(0, char::len_utf8);
+            for i in 0..self.current.dense.len() {
+                let pc = self.current.dense[i];
+                let accepted = match &self.program.insts[pc] {
+                    Inst::Char(expected) => c == Some(*expected),
+                    Inst::Class(set) => c.is_some_and(|c| set.contains(c)),
+                    Inst::Match(_) if non_empty && self.current.slots(pc)[0] == Some(pos) => false,
+                    Inst::Match(_) => {
+                        matched = Some(self.current.slots(pc).to_vec());
+                        break;
+                    }
+                    _ => false,
+                };
+                if accepted {
+                    self.scratch.copy_from_slice(self.current.slots(pc));
+                    self.add_thread(false, pc + 1, next_pos);
+                }
+            }
+            std::mem::swap(&mut self.current, &mut self.next);
+            self.next.clear();
+            if c.is_none() {
+                break;
+            }
+            pos = next_pos;
+        }
+        self.current.clear();
+        matched
+    }
+
+    /// Runs every thread to the end of the input instead of stopping at the first match
+    fn run_all(&mut self, matched: &mut [bool]) {
+        let mut pos = 0;
+        loop {
+            self.add_thread(true, 0, pos);
+            let c = self.input[pos..].chars().next();
+            let next_pos = pos + c.map_or(0, char::len_utf8);
+            for i in 0..self.current.dense.len() {
+                let pc = self.current.dense[i];
+                let accepted = match &self.program.insts[pc] {
+                    Inst::Char(expected) => c == Some(*expected),
+                    Inst::Class(set) => c.is_some_and(|c| set.contains(c)),
+                    Inst::Match(pattern) => {
+                        matched[*pattern] = true;
+                        false
+                    }
+                    _ => false,
+                };
+                if accepted {
+                    self.add_thread(false, pc + 1, next_pos);
+                }
+            }
+            std::mem::swap(&mut self.current, &mut self.next);
+            self.next.clear();
+            if c.is_none() {
+                break;
+            }
+            pos = next_pos;
+        }
+        self.current.clear();
+    }
+
+    /// Follows epsilon transitions from `pc` and queues the consuming instructions reached,
+    /// `scratch` holds the capture slots of the thread being added
+    fn add_thread(&mut self, current: bool, pc: usize, pos: usize) {
+        let threads = if current { &mut self.current } else { &mut self.next };
+        self.stack.push(Frame::Explore(pc));
+        while let Some(frame) = self.stack.pop() {
+            let pc = match frame {
+                Frame::Explore(pc) => pc,
+                Frame::Restore(slot, value) => {
+                    self.scratch[slot] = value;
+                    continue;
+                }
+            };
+            if threads.contains(pc) {
+                continue;
+            }
+            threads.insert(pc);
+            match &self.program.insts[pc] {
+                Inst::Jmp(to) => self.stack.push(Frame::Explore(*to)),
+                Inst::Split(first, second) => {
+                    self.stack.push(Frame::Explore(*second));
+                    self.stack.push(Frame::Explore(*first));
+                }
+                Inst::Save(slot) => {
+                    // the old value comes back once this branch is explored
+                    self.stack.push(Frame::Restore(*slot, self.scratch[*slot]));
+                    self.scratch[*slot] = Some(pos);
+                    self.stack.push(Frame::Explore(pc + 1));
+                }
+                Inst::Look(look) => {
+                    if look.holds(self.input, pos) {
+                        self.stack.push(Frame::Explore(pc + 1));
+                    }
+                }
+                Inst::Backref(_) => unreachable!("backreferences are left to the backtracker"),
+                Inst::Char(_) | Inst::Class(_) | Inst::Match(_) => {
+                    let width = threads.width;
+                    threads.slots[pc * width..(pc + 1) * width].copy_from_slice(&self.scratch);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::compile;
+    use crate::parser::parse;
+
+    fn find(input: &str, pattern: &str) -> Option<Spans> {
+        find_at(&compile(&parse(pattern).unwrap()).unwrap(), input, 0)
+    }
+
+    #[test]
+    fn find_leftmost_first_match() {
+        assert_eq!(find("trust", "rust"), Some(vec![Some((1, 5))]));
+        assert_eq!(find("rust", "scala"), None);
+        assert_eq!(find("", "a*"), Some(vec![Some((0, 0))]));
+        assert_eq!(find("abc", "a|ab"), Some(vec![Some((0, 1))]));
+        assert_eq!(find("aaaaa", "a{2,3}"), Some(vec![Some((0, 3))]));
+    }
+
+    #[test]
+    fn find_captures() {
+        assert_eq!(find("abab", "(ab)+"), Some(vec![Some((0, 4)), Some((2, 4))]));
+        assert_eq!(find("ct", "c(a)?t"), Some(vec![Some((0, 2)), None]));
+        assert_eq!(find("xcd", "((c|d))d"), Some(vec![Some((1, 3)), Some((1, 2)), Some((1, 2))]));
+    }
+
+    #[test]
+    fn find_anchored() {
+        assert_eq!(find("rust", "^ru"), Some(vec![Some((0, 2))]));
+        assert_eq!(find("trust", "^ru"), None);
+        assert_eq!(find("rust", "st$"), Some(vec![Some((2, 4))]));
+    }
+
+    #[test]
+    fn find_every_matching_pattern() {
+        let asts = ["ab", "b$", "c", "^a"].map(|pattern| parse(pattern).unwrap());
+        let asts: Vec<_> = asts.iter().enumerate().collect();
+        let program = crate::compile::compile_set(&asts).unwrap();
+        let mut matched = [false; 4];
+        which_match(&program, "xab", &mut matched);
+        assert_eq!(matched, [true, true, false, false]);
+    }
+
+    #[test]
+    fn run_in_linear_time() {
+        let input = "a".repeat(10_000);
+        assert_eq!(find(&input, "(a+)+b"), None);
+        assert_eq!(find(&input, "(a*)*$").map(|c| c[0]), Some(Some((0, 10_000))));
+    }
+}
@@ -0,0 +1,512 @@
+use crate::backtrack::{self, Spans};
+use crate::compile::{compile, compile_backtrack, Program};
+use crate::dfa::Dfa;
+use crate::error::PatternError;
+use crate::literal::{pure_literal, Prefilter};
+use crate::parser::{parse_literal, parse_with, Ast, Flags, Look};
+use crate::pikevm;
+use std::fmt;
+use std::ops::Range;
+use std::sync::Mutex;
+
+/// Byte offsets of a match, `captures[0]` spans the whole match and `captures[i]` group `i`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub start: usize,
+    pub end: usize,
+    pub captures: Vec<Option<Range<usize>>>,
+}
+
+impl Match {
+    fn from_spans(spans: Spans) -> Option<Match> {
+        let (start, end) = spans.first().copied().flatten()?;
+        Some(Match {
+            start,
+            end,
+            captures: spans
+                .into_iter()
+                .map(|span| span.map(|(start, end)| start..end))
+                .collect(),
+        })
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+}
+
+/// Match together with the haystack it was found in, for reading capture groups as text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Captures<'h> {
+    haystack: &'h str,
+    m: Match,
+}
+
+impl<'h> Captures<'h> {
+    /// Text of group `index`, or `None` when the group did not take part in the match
+    pub fn get(&self, index: usize) -> Option<&'h str> {
+        let range = self.m.captures.get(index)?.clone()?;
+        Some(&self.haystack[range])
+    }
+
+    /// Number of groups including the implicit group 0
+    pub fn len(&self) -> usize {
+        self.m.captures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.m.captures.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Option<&'h str>> + '_ {
+        (0..self.len()).map(|index| self.get(index))
+    }
+
+    pub fn as_match(&self) -> &Match {
+        &self.m
+    }
+}
+
+/// Compiled pattern, safe to share between threads
+pub struct Regex {
+    pattern: String,
+    ast: Ast,
+    program: Program,
+    /// Cleared by backreferences, which only the backtracker runs
+    regular: bool,
+    dfa: Option<Mutex<Dfa>>,
+    prefilter: Option<Prefilter>,
+    /// Set when the pattern is plain text that `prefilter` finds exactly, so no automaton has to run
+    literal: bool,
+}
+
+/// Configures how a pattern is compiled
+#[derive(Debug, Clone)]
+pub struct RegexBuilder {
+    pattern: String,
+    case_insensitive: bool,
+    smart_case: bool,
+    fixed_strings: bool,
+    whole_word: bool,
+    whole_line: bool,
+    unicode: bool,
+}
+
+impl RegexBuilder {
+    pub fn new(pattern: &str) -> Self {
+        RegexBuilder {
+            pattern: pattern.to_string(),
+            case_insensitive: false,
+            smart_case: false,
+            fixed_strings: false,
+            whole_word: false,
+            whole_line: false,
+            unicode: false,
+        }
+    }
+
+    /// Matches letters regardless of case using Unicode simple case folding
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut Self {
+        self.case_insensitive = yes;
+        self
+    }
+
+    /// Matches case insensitively unless the pattern contains an uppercase literal
+    pub fn smart_case(&mut self, yes: bool) -> &mut Self {
+        self.smart_case = yes;
+        self
+    }
+
+    /// Takes the pattern as plain text, without any special chars
+    pub fn fixed_strings(&mut self, yes: bool) -> &mut Self {
+        self.fixed_strings = yes;
+        self
+    }
+
+    /// Matches only where the pattern is not preceded or followed by a word char
+    pub fn whole_word(&mut self, yes: bool) -> &mut Self {
+        self.whole_word = yes;
+        self
+    }
+
+    /// Matches only where the pattern spans the whole input, takes precedence over [`whole_word`](Self::whole_word)
+    pub fn whole_line(&mut self, yes: bool) -> &mut Self {
+        self.whole_line = yes;
+        self
+    }
+
+    /// Gives `\d`, `\s`, `\w` and word boundaries their Unicode definitions instead of ASCII ones
+    pub fn unicode(&mut self, yes: bool) -> &mut Self {
+        self.unicode = yes;
+        self
+    }
+
+    pub fn build(&self) -> Result<Regex, PatternError> {
+        let parse = |flags| {
+            if self.fixed_strings {
+                Ok(parse_literal(&self.pattern, flags))
+            } else {
+                parse_with(&self.pattern, flags)
+            }
+        };
+        let unicode = self.unicode;
+        let mut ast = parse(Flags { case_insensitive: self.case_insensitive, unicode })?;
+        if self.smart_case && !self.case_insensitive && !ast.uppercase {
+            ast = parse(Flags { case_insensitive: true, unicode })?;
+        }
+        if self.whole_line {
+            ast.surround(Look::Start, Look::End);
+        } else if self.whole_word {
+            ast.surround(Look::WordStartHalf { unicode }, Look::WordEndHalf { unicode });
+        }
+        Ok(Regex::from_ast(&self.pattern, ast))
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, PatternError> {
+        RegexBuilder::new(pattern).build()
+    }
+
+    fn from_ast(pattern: &str, ast: Ast) -> Regex {
+        let (program, regular) = match compile(&ast) {
+            Some(program) => (program, true),
+            None => (compile_backtrack(&ast), false),
+        };
+        let dfa = Dfa::new(&program).map(Mutex::new);
+        let literal = pure_literal(&ast).filter(|literal| {
+            !literal.text.is_empty() && !literal.folds_non_ascii && ast.groups == 0
+        });
+        let prefilter = match &literal {
+            Some(literal) => Prefilter::from_literals(vec![literal.text.clone()], ast.flags.case_insensitive),
+            None => Prefilter::new(&ast),
+        };
+        let literal = literal.is_some() && prefilter.is_some();
+        Regex { pattern: pattern.to_string(), ast, program, regular, dfa, prefilter, literal }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    pub(crate) fn ast(&self) -> &Ast {
+        &self.ast
+    }
+
+    /// Whether the pattern compiles to an NFA, only backreferences prevent that
+    pub(crate) fn is_regular(&self) -> bool {
+        self.regular
+    }
+
+    /// Answers with the lazy DFA when it is available and not busy in another thread
+    pub fn is_match(&self, input: &str) -> bool {
+        if !self.is_candidate(input, 0) {
+            return false;
+        }
+        if self.literal {
+            return true;
+        }
+        if let Some(dfa) = &self.dfa
+            && let Ok(mut dfa) = dfa.try_lock()
+            && let Some(matched) = dfa.is_match(&self.program, input)
+        {
+            return matched;
+        }
+        self.find_at(input, 0).is_some()
+    }
+
+    /// Leftmost match in `haystack`, alternatives are tried in the order they are written
+    pub fn find(&self, haystack: &str) -> Option<Match> {
+        self.find_at(haystack, 0)
+    }
+
+    /// Successive non-overlapping matches, an empty match right after a previous match is skipped
+    pub fn find_iter<'r, 'h>(&'r self, haystack: &'h str) -> Matches<'r, 'h> {
+        Matches { regex: self, haystack, pos: Some(0), last_end: None }
+    }
+
+    pub fn captures<'h>(&self, haystack: &'h str) -> Option<Captures<'h>> {
+        self.find(haystack).map(|m| Captures { haystack, m })
+    }
+
+    pub fn captures_iter<'r, 'h>(&'r self, haystack: &'h str) -> impl Iterator<Item = Captures<'h>> + 'r
+    where
+        'h: 'r,
+    {
+        self.find_iter(haystack).map(move |m| Captures { haystack, m })
+    }
+
+    /// Runs the Pike VM when the pattern is regular and falls back to backtracking for backreferences,
+    /// plain text is found by substring search alone
+    pub fn find_at(&self, input: &str, start: usize) -> Option<Match> {
+        self.search(input, start, false)
+    }
+
+    /// Leftmost match that is not empty, where the pattern matches both the empty string and some
+    /// text at the same position the match of the text is taken
+    pub fn find_non_empty_at(&self, input: &str, start: usize) -> Option<Match> {
+        self.search(input, start, true)
+    }
+
+    fn search(&self, input: &str, start: usize, non_empty: bool) -> Option<Match> {
+        if self.literal
+            && let Some(prefilter) = &self.prefilter
+        {
+            let (start, end) = prefilter.find(input, start)?;
+            return Some(Match { start, end, captures: vec![Some(start..end)] });
+        }
+        if !self.is_candidate(input, start) {
+            return None;
+        }
+        let case_insensitive = self.ast.flags.case_insensitive;
+        let spans = match (self.regular, non_empty) {
+            (true, false) => pikevm::find_at(&self.program, input, start),
+            (true, true) => pikevm::find_non_empty_at(&self.program, input, start),
+            (false, false) => backtrack::find_at(&self.program, case_insensitive, input, start),
+            (false, true) => backtrack::find_non_empty_at(&self.program, case_insensitive, input, start),
+        };
+        spans.and_then(Match::from_spans)
+    }
+
+    fn is_candidate(&self, input: &str, start: usize) -> bool {
+        self.prefilter
+            .as_ref()
+            .is_none_or(|prefilter| prefilter.is_candidate(input, start))
+    }
+}
+
+impl fmt::Debug for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Regex").field(&self.pattern).finish()
+    }
+}
+
+impl fmt::Display for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+/// Iterator returned by [`Regex::find_iter`]
+pub struct Matches<'r, 'h> {
+    regex: &'r Regex,
+    haystack: &'h str,
+    pos: Option<usize>,
+    last_end: Option<usize>,
+}
+
+impl Iterator for Matches<'_, '_> {
+    type Item = Match;
+
+    fn next(&mut self) -> Option<Match> {
+        loop {
+            let m = self.regex.find_at(self.haystack, self.pos?)?;
+            self.pos = if m.is_empty() {
+                self.haystack[m.end..].chars().next().map(|c| m.end + c.len_utf8())
+            } else {
+                Some(m.end)
+            };
+            if m.is_empty() && self.last_end == Some(m.end) {
+                continue;
+            }
+            self.last_end = Some(m.end);
+            return Some(m);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pick_engine() {
+        assert!(Regex::new("(a+)+b").unwrap().regular);
+        assert!(!Regex::new("(a)\\1").unwrap().regular);
+    }
+
+    #[test]
+    fn is_match_with_any_engine() {
+        assert!(Regex::new("ba+ags").unwrap().is_match("xbaaags"));
+        assert!(!Regex::new("ba+ags").unwrap().is_match("xbags"));
+        assert!(Regex::new("(a)x\\1").unwrap().is_match("axa"));
+        assert!(!Regex::new("(a)x\\1").unwrap().is_match("axb"));
+        assert!(!Regex::new("(a)x\\1").unwrap().is_match("aya"));
+    }
+
+    #[test]
+    fn match_letters_in_both_cases() {
+        assert!(Regex::new("[Ee]rror").unwrap().is_match("Error"));
+        assert!(Regex::new("[Aa]b?").unwrap().is_match("A"));
+        assert_eq!(Regex::new("[Aa]b?").unwrap().find("xAb").map(|m| m.range()), Some(1..3));
+        assert!(Regex::new("[Ee]rror \\d").unwrap().is_match("an Error 1"));
+        assert!(Regex::new("[Ee]rror+").unwrap().is_match("Error"));
+        assert!(!Regex::new("[Ee]rror+").unwrap().is_match("ERROR"));
+    }
+
+    #[test]
+    fn find_match_spans() {
+        let regex = Regex::new("(\\w+)-(\\d)?").unwrap();
+        assert_eq!(regex.find_at("œ ab-x", 0), Some(Match {
+            start: 3,
+            end: 6,
+            captures: vec![Some(3..6), Some(3..5), None],
+        }));
+        assert_eq!(regex.find_at("œ ab-x", 4), Some(Match {
+            start: 4,
+            end: 6,
+            captures: vec![Some(4..6), Some(4..5), None],
+        }));
+    }
+
+    #[test]
+    fn find_non_empty_matches() {
+        let regex = Regex::new("x*|a").unwrap();
+        assert_eq!(regex.find_at("a", 0).map(|m| m.range()), Some(0..0));
+        assert_eq!(regex.find_non_empty_at("ba", 0).map(|m| m.range()), Some(1..2));
+        assert_eq!(regex.find_non_empty_at("b", 0), None);
+        let regex = Regex::new("(x)\\1*|a").unwrap();
+        assert_eq!(regex.find_non_empty_at("xa", 1).map(|m| m.range()), Some(1..2));
+        let regex = Regex::new("()\\1|ab").unwrap();
+        assert_eq!(regex.find_non_empty_at("ab", 0).map(|m| m.range()), Some(0..2));
+    }
+
+    #[test]
+    fn search_fixed_strings() {
+        let regex = RegexBuilder::new("a.(b").fixed_strings(true).build().unwrap();
+        assert!(regex.literal);
+        assert_eq!(regex.find("xa.(b").map(|m| m.range()), Some(1..5));
+        assert!(!regex.is_match("ax(b"));
+        let regex = RegexBuilder::new("A[1]").fixed_strings(true).case_insensitive(true).build().unwrap();
+        assert_eq!(regex.find_iter("a[1] A[1]").map(|m| m.range()).collect::<Vec<_>>(), vec![0..4, 5..9]);
+        let regex = RegexBuilder::new("ok").fixed_strings(true).case_insensitive(true).build().unwrap();
+        assert!(!regex.literal);
+        assert!(regex.is_match("O\u{212A}"));
+    }
+
+    #[test]
+    fn match_whole_words_and_lines() {
+        let regex = RegexBuilder::new("ab*").whole_word(true).build().unwrap();
+        assert_eq!(regex.find("abbbc ab").map(|m| m.range()), Some(6..8));
+        let regex = RegexBuilder::new("foo").whole_word(true).build().unwrap();
+        assert_eq!(regex.find("foobar foo_x foo.").map(|m| m.range()), Some(13..16));
+        assert!(!regex.is_match("food"));
+        let regex = RegexBuilder::new("a|ab").whole_line(true).build().unwrap();
+        assert_eq!(regex.find("ab").map(|m| m.range()), Some(0..2));
+        assert!(!regex.is_match("abc"));
+        let regex = RegexBuilder::new("a.b").fixed_strings(true).whole_word(true).build().unwrap();
+        assert!(!regex.is_match("xa.b"));
+        assert!(regex.is_match("(a.b)"));
+    }
+
+    #[test]
+    fn match_shorthand_classes() {
+        let regex = Regex::new("\\S+[\\s,;]\\D\\W").unwrap();
+        assert_eq!(regex.find("ab;1, x!").map(|m| m.range()), Some(0..8));
+        assert!(!Regex::new("\\d\\s").unwrap().is_match("٣\u{A0}"));
+        let regex = RegexBuilder::new("\\d\\s\\w+\\b").unicode(true).build().unwrap();
+        assert_eq!(regex.find("٣\u{A0}héllo").map(|m| m.range()), Some(0..10));
+        let regex = RegexBuilder::new("[^\\W\\d]+").unicode(true).build().unwrap();
+        assert_eq!(regex.find("42 œuvre").map(|m| m.range()), Some(3..9));
+    }
+
+    #[test]
+    fn match_unicode_properties() {
+        let regex = Regex::new("\\p{Greek}+ \\P{ASCII}").unwrap();
+        assert_eq!(regex.find("id λόγος № 5").map(|m| m.range()), Some(3..17));
+        let regex = RegexBuilder::new("\\p{Lu}\\d").case_insensitive(true).build().unwrap();
+        assert!(regex.is_match("é1"));
+        assert!(!Regex::new("\\p{Lu}").unwrap().is_match("é1"));
+    }
+
+    #[test]
+    fn match_word_boundaries() {
+        let regex = Regex::new("\\bfoo\\b").unwrap();
+        assert!(regex.dfa.is_some());
+        assert!(regex.is_match("a foo") && !regex.is_match("a food"));
+        assert_eq!(regex.find("foobar foo.").map(|m| m.range()), Some(7..10));
+        let regex = Regex::new("(\\<ab|cd\\>)").unwrap();
+        assert_eq!(regex.find("xab cde cd").map(|m| m.range()), Some(8..10));
+        let regex = Regex::new("a\\Bb|\\bc").unwrap();
+        assert_eq!(regex.find_iter("a b ab bc c").map(|m| m.range()).collect::<Vec<_>>(), vec![4..6, 10..11]);
+        let regex = Regex::new("(\\w+)\\b \\1\\>").unwrap();
+        assert_eq!(regex.find("the then then").map(|m| m.range()), Some(4..13));
+    }
+
+    #[test]
+    fn ignore_case() {
+        let regex = RegexBuilder::new("straße [abc]").case_insensitive(true).build().unwrap();
+        assert!(!regex.is_match("STRASSE B"));
+        assert!(regex.is_match("STRAẞE B"));
+        assert!(regex.is_match("Straße c"));
+        assert!(!regex.is_match("Strasse d"));
+    }
+
+    #[test]
+    fn smart_case() {
+        let regex = RegexBuilder::new("error\\d").smart_case(true).build().unwrap();
+        assert!(regex.is_match("ERROR1"));
+        let regex = RegexBuilder::new("Error\\d").smart_case(true).build().unwrap();
+        assert!(!regex.is_match("ERROR1"));
+        assert!(regex.is_match("Error1"));
+        let regex = RegexBuilder::new("\\w+\\d").smart_case(true).build().unwrap();
+        assert!(regex.is_match("ab1"));
+    }
+
+    #[test]
+    fn iterate_matches() {
+        let regex = Regex::new("a*").unwrap();
+        let spans: Vec<_> = regex.find_iter("baaœa").map(|m| m.range()).collect();
+        assert_eq!(spans, vec![0..0, 1..3, 5..6]);
+        let regex = Regex::new("\\d+").unwrap();
+        let spans: Vec<_> = regex.find_iter("a1 23 456").map(|m| m.range()).collect();
+        assert_eq!(spans, vec![1..2, 3..5, 6..9]);
+    }
+
+    #[test]
+    fn read_captures() {
+        let regex = Regex::new("(\\w+)@(\\w+)(-\\d)?").unwrap();
+        let captures = regex.captures("mail: rust@lang").unwrap();
+        assert_eq!(captures.iter().collect::<Vec<_>>(), vec![Some("rust@lang"), Some("rust"), Some("lang"), None]);
+        let names: Vec<_> = regex.captures_iter("a@b c@d").map(|c| c.get(1)).collect();
+        assert_eq!(names, vec![Some("a"), Some("c")]);
+    }
+
+    #[test]
+    fn engines_agree() {
+        let cases = [
+            ("baaags", "ba+ags"),
+            ("rust", "(r?[au]s|scala)t?"),
+            ("abcdef", "(ab|ef|cd){3}"),
+            ("aaaaa", "a{2,3}"),
+            ("aaaacc", "a{1,}cc"),
+            ("xabab", "((a)b)*$"),
+            ("¾®_ediœ1", "\\wedi[^x]\\d"),
+            ("aa", "(|a)?"),
+            ("a abb", "(|a){1,3}.{1,3}"),
+            ("ab", "(a|)*b"),
+            ("aab", "((a*)*|b)+"),
+        ];
+        for (input, pattern) in cases {
+            let regex = Regex::new(pattern).unwrap();
+            let program = &regex.program;
+            let mut dfa = Dfa::new(program).unwrap();
+            assert_eq!(dfa.is_match(program, input), Some(true), "{} on {}", pattern, input);
+            assert_eq!(
+                pikevm::find_at(program, input, 0),
+                backtrack::find_at(program, false, input, 0),
+                "{} on {}",
+                pattern,
+                input
+            );
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use crate::compile::{compile_set, Program};
+use crate::dfa::Dfa;
+use crate::error::PatternError;
+use crate::literal::{pure_literal, Prefilter};
+use crate::pikevm;
+use crate::regex::{Regex, RegexBuilder};
+use aho_corasick::{AhoCorasick, MatchKind};
+use std::fmt;
+use std::sync::Mutex;
+
+/// Patterns searched together in one pass, reporting which of them match
+pub struct RegexSet {
+    regexes: Vec<Regex>,
+    literals: Option<Literals>,
+    program: Option<Program>,
+    dfa: Option<Mutex<Dfa>>,
+    /// Skips lines lacking the required literals of all patterns in `program`
+    prefilter: Option<Prefilter>,
+    /// Patterns left out of `program` because backreferences make them irregular
+    irregular: Vec<usize>,
+}
+
+/// Aho-Corasick automata used when every pattern is plain text
+struct Literals {
+    first: AhoCorasick,
+    all: AhoCorasick,
+    /// Case folding of the patterns adds non-ASCII chars, so only ASCII input is searched this way
+    ascii_input_only: bool,
+}
+
+/// Configures how the patterns of a set are compiled, every pattern gets the same options
+#[derive(Debug, Clone)]
+pub struct RegexSetBuilder {
+    patterns: Vec<String>,
+    case_insensitive: bool,
+    smart_case: bool,
+    fixed_strings: bool,
+    whole_word: bool,
+    whole_line: bool,
+    unicode: bool,
+}
+
+impl RegexSetBuilder {
+    pub fn new<I, S>(patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        RegexSetBuilder {
+            patterns: patterns.into_iter().map(|pattern| pattern.as_ref().to_string()).collect(),
+            case_insensitive: false,
+            smart_case: false,
+            fixed_strings: false,
+            whole_word: false,
+            whole_line: false,
+            unicode: false,
+        }
+    }
+
+    /// Matches letters regardless of case using Unicode simple case folding
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut Self {
+        self.case_insensitive = yes;
+        self
+    }
+
+    /// Matches a pattern case insensitively unless it contains an uppercase literal
+    pub fn smart_case(&mut self, yes: bool) -> &mut Self {
+        self.smart_case = yes;
+        self
+    }
+
+    /// Takes every pattern as plain text, without any special chars
+    pub fn fixed_strings(&mut self, yes: bool) -> &mut Self {
+        self.fixed_strings = yes;
+        self
+    }
+
+    /// Matches a pattern only where it is not preceded or followed by a word char
+    pub fn whole_word(&mut self, yes: bool) -> &mut Self {
+        self.whole_word = yes;
+        self
+    }
+
+    /// Matches a pattern only where it spans the whole input, takes precedence over [`whole_word`](Self::whole_word)
+    pub fn whole_line(&mut self, yes: bool) -> &mut Self {
+        self.whole_line = yes;
+        self
+    }
+
+    /// Uses the Unicode definitions of shorthand classes and word boundaries in every pattern
+    pub fn unicode(&mut self, yes: bool) -> &mut Self {
+        self.unicode = yes;
+        self
+    }
+
+    /// Fails with the error of the first invalid pattern
+    pub fn build(&self) -> Result<RegexSet, PatternError> {
+        let regexes = self
+            .patterns
+            .iter()
+            .map(|pattern| {
+                RegexBuilder::new(pattern)
+                    .case_insensitive(self.case_insensitive)
+                    .smart_case(self.smart_case)
+                    .fixed_strings(self.fixed_strings)
+                    .whole_word(self.whole_word)
+                    .whole_line(self.whole_line)
+                    .unicode(self.unicode)
+                    .build()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(RegexSet::from_regexes(regexes))
+    }
+}
+
+impl RegexSet {
+    pub fn new<I, S>(patterns: I) -> Result<RegexSet, PatternError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        RegexSetBuilder::new(patterns).build()
+    }
+
+    /// Uses Aho-Corasick when all patterns are plain text, otherwise one NFA for all regular patterns
+    fn from_regexes(regexes: Vec<Regex>) -> RegexSet {
+        let literals = Literals::new(&regexes);
+        let mut asts = Vec::new();
+        let mut irregular = Vec::new();
+        for (index, regex) in regexes.iter().enumerate() {
+            if !regex.is_regular() {
+                irregular.push(index);
+            } else if literals.as_ref().is_none_or(|literals| literals.ascii_input_only) {
+                asts.push((index, regex.ast()));
+            }
+        }
+        let program = compile_set(&asts);
+        let dfa = program.as_ref().and_then(Dfa::new).map(Mutex::new);
+        let prefilter = Prefilter::for_patterns(asts.iter().map(|(_, ast)| *ast));
+        RegexSet { regexes, literals, program, dfa, prefilter, irregular }
+    }
+
+    /// Number of patterns
+    pub fn len(&self) -> usize {
+        self.regexes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regexes.is_empty()
+    }
+
+    /// Patterns compiled one by one, for finding the spans of the patterns [`RegexSet::matches`] reports
+    pub fn regexes(&self) -> &[Regex] {
+        &self.regexes
+    }
+
+    /// Whether any pattern matches, answered with the lazy DFA when it is available and not busy
+    pub fn is_match(&self, input: &str) -> bool {
+        if let Some(literals) = self.literals_for(input) {
+            return literals.first.is_match(input);
+        }
+        let regular = self.program.as_ref().filter(|_| self.is_candidate(input)).is_some_and(|program| {
+            let answer = self
+                .dfa
+                .as_ref()
+                .and_then(|dfa| dfa.try_lock().ok())
+                .and_then(|mut dfa| dfa.is_match(program, input));
+            answer.unwrap_or_else(|| pikevm::find_at(program, input, 0).is_some())
+        });
+        regular || self.irregular.iter().any(|&index| self.regexes[index].is_match(input))
+    }
+
+    /// Indices of all patterns that match somewhere in `input`, in increasing order
+    pub fn matches(&self, input: &str) -> Vec<usize> {
+        let mut matched = vec![false; self.regexes.len()];
+        if let Some(literals) = self.literals_for(input) {
+            for m in literals.all.find_overlapping_iter(input) {
+                matched[m.pattern().as_usize()] = true;
+            }
+        } else if let Some(program) = &self.program
+            && self.is_candidate(input)
+        {
+            let answered = self
+                .dfa
+                .as_ref()
+                .and_then(|dfa| dfa.try_lock().ok())
+                .and_then(|mut dfa| dfa.which_match(program, input, &mut matched))
+                .is_some();
+            if !answered {
+                pikevm::which_match(program, input, &mut matched);
+            }
+        }
+        for &index in &self.irregular {
+            matched[index] = self.regexes[index].is_match(input);
+        }
+        matched
+            .iter()
+            .enumerate()
+            .filter_map(|(index, &matched)| matched.then_some(index))
+            .collect()
+    }
+
+    fn is_candidate(&self, input: &str) -> bool {
+        self.prefilter
+            .as_ref()
+            .is_none_or(|prefilter| prefilter.is_candidate(input, 0))
+    }
+
+    fn literals_for(&self, input: &str) -> Option<&Literals> {
+        self.literals
+            .as_ref()
+            .filter(|literals| !literals.ascii_input_only || input.is_ascii())
+    }
+}
+
+impl Literals {
+    /// Requires every pattern to be plain text with the same case sensitivity
+    fn new(regexes: &[Regex]) -> Option<Literals> {
+        let case_insensitive = regexes.first()?.ast().flags.case_insensitive;
+        if regexes.iter().any(|regex| regex.ast().flags.case_insensitive != case_insensitive) {
+            return None;
+        }
+        let literals = regexes
+            .iter()
+            .map(|regex| pure_literal(regex.ast()))
+            .collect::<Option<Vec<_>>>()?;
+        let ascii_input_only = literals.iter().any(|literal| literal.folds_non_ascii);
+        let literals: Vec<String> = literals.into_iter().map(|literal| literal.text).collect();
+        let build = |kind| {
+            AhoCorasick::builder()
+                .match_kind(kind)
+                .ascii_case_insensitive(case_insensitive)
+                .build(&literals)
+                .ok()
+        };
+        Some(Literals {
+            first: build(MatchKind::LeftmostFirst)?,
+            all: build(MatchKind::Standard)?,
+            ascii_input_only,
+        })
+    }
+}
+
+impl fmt::Debug for RegexSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RegexSet")
+            .field(&self.regexes.iter().map(Regex::as_str).collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_any_pattern() {
+        let set = RegexSet::new(["\\d+", "^b", "(a)x\\1"]).unwrap();
+        assert!(set.program.is_some() && set.literals.is_none());
+        assert_eq!(set.irregular, vec![2]);
+        assert_eq!(set.matches("b2 axa"), vec![0, 1, 2]);
+        assert_eq!(set.matches("ab"), Vec::<usize>::new());
+        assert!(set.is_match("axa"));
+        assert!(!set.is_match("ab"));
+        assert!(!RegexSet::new(Vec::<String>::new()).unwrap().is_match("ab"));
+    }
+
+    #[test]
+    fn match_whole_words() {
+        let set = RegexSetBuilder::new(["o", "foo."]).whole_word(true).build().unwrap();
+        assert!(set.dfa.is_some());
+        assert_eq!(set.matches("foobar foo."), vec![1]);
+        assert!(set.is_match("food"));
+        assert!(!set.is_match("foo_d"));
+    }
+
+    #[test]
+    fn skip_lines_without_required_literals() {
+        let set = RegexSet::new(["zzzq \\d+", "(ab|cd)+x", "(a)\\1"]).unwrap();
+        assert!(set.prefilter.is_some());
+        assert_eq!(set.matches("zzzq 12 cdx aa"), vec![0, 1, 2]);
+        assert_eq!(set.matches("zzzq x aa"), vec![2]);
+        assert!(!set.is_match("zzzq x"));
+        assert!(RegexSet::new(["zzzq \\d+", "\\d+"]).unwrap().prefilter.is_none());
+        let set = RegexSetBuilder::new(["zzzq \\d", "Ab+"]).smart_case(true).build().unwrap();
+        assert_eq!(set.matches("ZZZQ 1 abb"), vec![0]);
+    }
+
+    #[test]
+    fn search_literals_with_aho_corasick() {
+        let set = RegexSetBuilder::new(["rum", "Cola", "ol"]).case_insensitive(true).build().unwrap();
+        assert!(set.literals.is_some() && set.program.is_none());
+        assert_eq!(set.matches("RUM and cola"), vec![0, 1, 2]);
+        assert!(!set.is_match("ru"));
+        let set = RegexSetBuilder::new(["rust", "Scala"]).smart_case(true).build().unwrap();
+        assert!(set.literals.is_none());
+        assert_eq!(set.matches("RUST Scala"), vec![0, 1]);
+        let set = RegexSetBuilder::new(["risk", "(a)"]).fixed_strings(true).case_insensitive(true).build().unwrap();
+        assert!(set.literals.as_ref().is_some_and(|literals| literals.ascii_input_only) && set.program.is_some());
+        assert_eq!(set.matches("RISK (A)"), vec![0, 1]);
+        assert_eq!(set.matches("RIſK"), vec![0]);
+        let set = RegexSet::new(["[Ee]rror", "[Aa]b?"]).unwrap();
+        assert!(set.literals.is_none());
+        assert_eq!(set.matches("Error A"), vec![0, 1]);
+    }
+
+    #[test]
+    fn scale_to_thousands_of_patterns() {
+        let literals: Vec<String> = (0..5000).map(|i| format!("ioc-{}-", i)).collect();
+        let set = RegexSet::new(&literals).unwrap();
+        assert_eq!(set.matches("seen ioc-42- and ioc-4999-"), vec![42, 4999]);
+        let patterns: Vec<String> = (0..2000).map(|i| format!("host{}[.][a-z]+", i)).collect();
+        let set = RegexSet::new(&patterns).unwrap();
+        assert_eq!(set.matches("host7.lan host1999.com host20"), vec![7, 1999]);
+        assert!(!set.is_match("host3-lan"));
+    }
+}
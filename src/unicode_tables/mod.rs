@@ -0,0 +1,8 @@
+pub mod case_folding;
+pub mod general_category;
+pub mod perl;
+pub mod property;
+pub mod script;
+
+/// Sorted, non-overlapping inclusive ranges of chars
+pub type Table = &'static [(char, char)];
@@ -0,0 +1,216 @@
+//! Writes the Unicode tables under `src/unicode_tables`, run from the repository root with
+//! `cargo run --manifest-path tools/generate-tables/Cargo.toml -- UCD_DIR`,
+//! where `UCD_DIR` holds the files of the Unicode Character Database
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const HEADER: &str = "// Generated by tools/generate-tables, do not edit\n\n";
+
+type Ranges = Vec<(u32, u32)>;
+
+fn main() -> io::Result<()> {
+    let ucd = env::args().nth(1).ok_or_else(|| io::Error::other("usage: generate-tables UCD_DIR"))?;
+    let ucd = Path::new(&ucd);
+    fs::write("src/unicode_tables/case_folding.rs", case_folding(ucd)?)?;
+    fs::write("src/unicode_tables/perl.rs", perl(ucd)?)?;
+    fs::write("src/unicode_tables/general_category.rs", general_category(ucd)?)?;
+    fs::write("src/unicode_tables/script.rs", script(ucd)?)?;
+    fs::write("src/unicode_tables/property.rs", property(ucd)?)?;
+    Ok(())
+}
+
+/// Ranges of every value in a UCD file made of `start..end ; value # comment` lines
+fn read_ucd(path: &Path) -> io::Result<BTreeMap<String, Ranges>> {
+    let mut values: BTreeMap<String, Ranges> = BTreeMap::new();
+    for line in fs::read_to_string(path)?.lines() {
+        let line = line.split('#').next().unwrap_or_default();
+        let Some((range, value)) = line.split_once(';') else {
+            continue;
+        };
+        let (start, end) = range.trim().split_once("..").unwrap_or((range.trim(), range.trim()));
+        let parse = |hex| u32::from_str_radix(hex, 16).map_err(io::Error::other);
+        values.entry(value.trim().to_string()).or_default().push((parse(start)?, parse(end)?));
+    }
+    Ok(values)
+}
+
+/// Union of the ranges of `names`, which must all occur in `values`
+fn union(values: &BTreeMap<String, Ranges>, names: &[&str]) -> io::Result<Ranges> {
+    let mut ranges = Vec::new();
+    for name in names {
+        let found = values.get(*name).ok_or_else(|| io::Error::other(format!("missing property {}", name)))?;
+        ranges.extend_from_slice(found);
+    }
+    Ok(merge(ranges))
+}
+
+fn merge(mut ranges: Ranges) -> Ranges {
+    ranges.sort();
+    let mut merged: Ranges = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// General categories with their long names, and the groups named by their first letter
+const GENERAL_CATEGORIES: &[(&str, &str, &[&str])] = &[
+    ("C", "Other", &["Cc", "Cf", "Cn", "Co", "Cs"]),
+    ("Cc", "Control", &["Cc"]),
+    ("Cf", "Format", &["Cf"]),
+    ("Cn", "Unassigned", &["Cn"]),
+    ("Co", "Private_Use", &["Co"]),
+    ("Cs", "Surrogate", &["Cs"]),
+    ("L", "Letter", &["Ll", "Lm", "Lo", "Lt", "Lu"]),
+    ("LC", "Cased_Letter", &["Ll", "Lt", "Lu"]),
+    ("Ll", "Lowercase_Letter", &["Ll"]),
+    ("Lm", "Modifier_Letter", &["Lm"]),
+    ("Lo", "Other_Letter", &["Lo"]),
+    ("Lt", "Titlecase_Letter", &["Lt"]),
+    ("Lu", "Uppercase_Letter", &["Lu"]),
+    ("M", "Mark", &["Mc", "Me", "Mn"]),
+    ("Mc", "Spacing_Mark", &["Mc"]),
+    ("Me", "Enclosing_Mark", &["Me"]),
+    ("Mn", "Nonspacing_Mark", &["Mn"]),
+    ("N", "Number", &["Nd", "Nl", "No"]),
+    ("Nd", "Decimal_Number", &["Nd"]),
+    ("Nl", "Letter_Number", &["Nl"]),
+    ("No", "Other_Number", &["No"]),
+    ("P", "Punctuation", &["Pc", "Pd", "Pe", "Pf", "Pi", "Po", "Ps"]),
+    ("Pc", "Connector_Punctuation", &["Pc"]),
+    ("Pd", "Dash_Punctuation", &["Pd"]),
+    ("Pe", "Close_Punctuation", &["Pe"]),
+    ("Pf", "Final_Punctuation", &["Pf"]),
+    ("Pi", "Initial_Punctuation", &["Pi"]),
+    ("Po", "Other_Punctuation", &["Po"]),
+    ("Ps", "Open_Punctuation", &["Ps"]),
+    ("S", "Symbol", &["Sc", "Sk", "Sm", "So"]),
+    ("Sc", "Currency_Symbol", &["Sc"]),
+    ("Sk", "Modifier_Symbol", &["Sk"]),
+    ("Sm", "Math_Symbol", &["Sm"]),
+    ("So", "Other_Symbol", &["So"]),
+    ("Z", "Separator", &["Zl", "Zp", "Zs"]),
+    ("Zl", "Line_Separator", &["Zl"]),
+    ("Zp", "Paragraph_Separator", &["Zp"]),
+    ("Zs", "Space_Separator", &["Zs"]),
+];
+
+/// Tables of the two letter categories, the groups and long names point at their members
+fn general_category(ucd: &Path) -> io::Result<String> {
+    let categories = read_ucd(&ucd.join("extracted/DerivedGeneralCategory.txt"))?;
+    let mut names = Vec::new();
+    for &(short, long, members) in GENERAL_CATEGORIES {
+        let members: Vec<_> = members.iter().map(|member| member.to_string()).collect();
+        names.push((short.to_string(), members.clone()));
+        names.push((long.to_string(), members));
+    }
+    let tables = categories.into_iter().map(|(name, ranges)| (name, merge(ranges))).collect();
+    Ok(named_tables(tables, names))
+}
+
+fn script(ucd: &Path) -> io::Result<String> {
+    let scripts = read_ucd(&ucd.join("Scripts.txt"))?;
+    Ok(own_names(scripts))
+}
+
+/// Binary properties of `PropList.txt` and `DerivedCoreProperties.txt`
+fn property(ucd: &Path) -> io::Result<String> {
+    let mut properties = read_ucd(&ucd.join("PropList.txt"))?;
+    properties.append(&mut read_ucd(&ucd.join("DerivedCoreProperties.txt"))?);
+    Ok(own_names(properties))
+}
+
+fn own_names(values: BTreeMap<String, Ranges>) -> String {
+    let names = values.keys().map(|name| (name.clone(), vec![name.clone()])).collect();
+    let tables = values.into_iter().map(|(name, ranges)| (name, merge(ranges))).collect();
+    named_tables(tables, names)
+}
+
+/// Writes a `BY_NAME` slice sorted by name that lists the tables whose union every name stands for
+fn named_tables(tables: Vec<(String, Ranges)>, mut names: Vec<(String, Vec<String>)>) -> String {
+    names.sort();
+    let mut out = String::from(HEADER);
+    out.push_str("use super::Table;\n\n");
+    out.push_str("pub const BY_NAME: &[(&str, &[Table])] = &[\n");
+    for (name, members) in names {
+        let members = members.iter().map(|member| member.to_uppercase()).collect::<Vec<_>>().join(", ");
+        out.push_str(&format!("    ({:?}, &[{}]),\n", name, members));
+    }
+    out.push_str("];\n");
+    for (name, ranges) in tables {
+        out.push('\n');
+        table(&mut out, &name.to_uppercase(), &ranges);
+    }
+    out
+}
+
+/// Writes `ranges` as a `(char, char)` slice, leaving out the surrogates that are not chars
+fn table(out: &mut String, name: &str, ranges: &[(u32, u32)]) {
+    out.push_str(&format!("pub const {}: &[(char, char)] = &[\n", name));
+    for &(start, end) in ranges {
+        let parts = [(start, end.min(0xD7FF)), (start.max(0xE000), end)];
+        for (start, end) in parts.into_iter().filter(|(start, end)| start <= end) {
+            let (start, end) = (char::from_u32(start).unwrap(), char::from_u32(end).unwrap());
+            out.push_str(&format!("    ({:?}, {:?}),\n", start, end));
+        }
+    }
+    out.push_str("];\n");
+}
+
+/// Unicode definitions of `\d`, `\s` and `\w` following UTS #18
+fn perl(ucd: &Path) -> io::Result<String> {
+    let categories = read_ucd(&ucd.join("extracted/DerivedGeneralCategory.txt"))?;
+    let mut properties = read_ucd(&ucd.join("PropList.txt"))?;
+    properties.append(&mut read_ucd(&ucd.join("DerivedCoreProperties.txt"))?);
+    let mut word = union(&properties, &["Alphabetic", "Join_Control"])?;
+    word.extend(union(&categories, &["Mn", "Mc", "Me", "Nd", "Pc"])?);
+    let mut out = String::from(HEADER);
+    table(&mut out, "DIGIT", &union(&categories, &["Nd"])?);
+    out.push('\n');
+    table(&mut out, "SPACE", &union(&properties, &["White_Space"])?);
+    out.push('\n');
+    table(&mut out, "WORD", &merge(word));
+    Ok(out)
+}
+
+/// Chars sharing a simple case folding, from the common and simple mappings of `CaseFolding.txt`
+fn case_folding(ucd: &Path) -> io::Result<String> {
+    let mut orbits: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+    for line in fs::read_to_string(ucd.join("CaseFolding.txt"))?.lines() {
+        let line = line.split('#').next().unwrap_or_default();
+        let fields: Vec<&str> = line.split(';').map(str::trim).collect();
+        let &[code, status, mapping, ..] = fields.as_slice() else {
+            continue;
+        };
+        if status != "C" && status != "S" {
+            continue;
+        }
+        let parse = |hex| u32::from_str_radix(hex, 16).map_err(io::Error::other);
+        let (code, mapping) = (parse(code)?, parse(mapping)?);
+        orbits.entry(mapping).or_insert_with(|| vec![mapping]).push(code);
+    }
+    let char = |c| char::from_u32(c).ok_or_else(|| io::Error::other(format!("invalid char {:X}", c)));
+    let mut table = BTreeMap::new();
+    for orbit in orbits.values_mut() {
+        orbit.sort();
+        for &c in orbit.iter() {
+            let others = orbit.iter().filter(|&&other| other != c).map(|&other| char(other));
+            table.insert(char(c)?, others.collect::<io::Result<Vec<_>>>()?);
+        }
+    }
+    let mut out = String::from(HEADER);
+    out.push_str("pub const CASE_FOLDING: &[(char, &[char])] = &[\n");
+    for (c, others) in table {
+        let others = others.iter().map(|o| format!("{:?}", o)).collect::<Vec<_>>().join(", ");
+        out.push_str(&format!("    ({:?}, &[{}]),\n", c, others));
+    }
+    out.push_str("];\n");
+    Ok(out)
+}